@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  sink::Sink,
+  state,
+  value::{key_literal, Value},
+};
+
+/// Progress of an interrupted export, saved next to the output as `{table}.checkpoint.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+  /// last `--index` key written, as an SQL literal
+  pub last_key: Option<String>,
+  /// byte length of the output up to and including the last checkpointed row
+  pub offset: u64,
+  /// rows written up to `offset`
+  pub rows: u64,
+}
+
+/// Periodically flushes the writer and records the last exported key
+pub struct Checkpointer {
+  path: String,
+  index_pos: usize,
+  every: u64,
+  pending: u64,
+  state: Checkpoint,
+}
+
+impl Checkpointer {
+  pub fn new(path: String, index_pos: usize, every: u64, state: Checkpoint) -> Self {
+    Checkpointer {
+      path,
+      index_pos,
+      every: every.max(1),
+      pending: 0,
+      state,
+    }
+  }
+
+  pub fn index_pos(&self) -> usize {
+    self.index_pos
+  }
+
+  /// Called after each row has been serialized; `key` is the row's `--index` value
+  pub fn observe(
+    &mut self,
+    key: &Value,
+    sink: &mut dyn Sink,
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    self.state.last_key = key_literal(key);
+    self.state.rows += 1;
+    self.pending += 1;
+    if self.pending >= self.every {
+      self.save(sink)?;
+    }
+
+    Ok(())
+  }
+
+  fn save(&mut self, sink: &mut dyn Sink) -> Result<(), Box<dyn std::error::Error>> {
+    sink.flush()?;
+    self.state.offset = sink.offset()?;
+    state::save(&self.state, &self.path)?;
+    self.pending = 0;
+
+    Ok(())
+  }
+
+  /// Removes the checkpoint once the export has completed
+  pub fn finish(self) -> std::io::Result<()> {
+    match std::fs::remove_file(&self.path) {
+      Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Orders the user query by the `--index` key, continuing after `after` when resuming
+pub fn resume_query(sql: &str, index: &str, after: Option<&str>) -> String {
+  let sql = sql.trim().trim_end_matches(';');
+  match after {
+    Some(key) => format!(
+      "SELECT * FROM ({}) t WHERE t.`{}` > {} ORDER BY t.`{}`",
+      sql, index, key, index
+    ),
+    None => format!("SELECT * FROM ({}) t ORDER BY t.`{}`", sql, index),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn resume_queries() {
+    assert_eq!(
+      resume_query(" SELECT * FROM t; ", "id", None),
+      "SELECT * FROM (SELECT * FROM t) t ORDER BY t.`id`"
+    );
+    assert_eq!(
+      resume_query("SELECT * FROM t", "code", Some("'a\\'b'")),
+      "SELECT * FROM (SELECT * FROM t) t WHERE t.`code` > 'a\\'b' ORDER BY t.`code`"
+    );
+  }
+}
@@ -0,0 +1,151 @@
+use crate::tables::glob_regex;
+
+/// Parses a `--rename OLD=NEW` pair
+pub fn parse_rename(spec: &str) -> Result<(String, String), String> {
+  match spec.split_once('=') {
+    Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+      Ok((old.to_string(), new.to_string()))
+    }
+    _ => Err(format!("expected OLD=NEW, got `{}`", spec)),
+  }
+}
+
+/// Positions of the output columns within the query columns: the `--columns` entries in the
+/// order given (a glob adds its matches in query order), or every column when there are none,
+/// minus the `--exclude-columns` matches
+pub fn project(
+  names: &[&str],
+  include: &[String],
+  exclude: &[String],
+) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+  let mut positions = Vec::new();
+  if include.is_empty() {
+    positions.extend(0..names.len());
+  }
+  for pattern in include {
+    let re = glob_regex(pattern)?;
+    let matches: Vec<usize> = (0..names.len())
+      .filter(|pos| re.is_match(names[*pos]))
+      .collect();
+    if matches.is_empty() {
+      return Err(format!("--columns entry `{}` matches no query column", pattern).into());
+    }
+    for pos in matches {
+      if !positions.contains(&pos) {
+        positions.push(pos);
+      }
+    }
+  }
+
+  let exclude = exclude
+    .iter()
+    .map(|pattern| glob_regex(pattern))
+    .collect::<Result<Vec<_>, _>>()?;
+  positions.retain(|pos| !exclude.iter().any(|re| re.is_match(names[*pos])));
+  if positions.is_empty() {
+    return Err("--columns and --exclude-columns leave no column to export".into());
+  }
+
+  Ok(positions)
+}
+
+/// Output header of each projected column after `--rename`. Repeated names are kept for
+/// formats where they are harmless, unless a rename is what makes them collide
+pub fn output_names(
+  names: &[&str],
+  projection: &[usize],
+  renames: &[(String, String)],
+  unique: bool,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+  for (old, _) in renames {
+    if !names.contains(&old.as_str()) {
+      return Err(format!("--rename column `{}` is not in the query result", old).into());
+    }
+  }
+
+  let mut output: Vec<String> = Vec::with_capacity(projection.len());
+  for pos in projection {
+    let name = renames
+      .iter()
+      .find(|(old, _)| old == names[*pos])
+      .map_or(names[*pos], |(_, new)| new.as_str());
+    if let Some(seen) = output.iter().position(|seen| seen == name) {
+      let renamed = name != names[*pos] || name != names[projection[seen]];
+      if unique || renamed {
+        return Err(format!("output column `{}` appears more than once", name).into());
+      }
+    }
+    output.push(name.to_string());
+  }
+
+  Ok(output)
+}
+
+/// Fails when `names` repeats a column, which MySQL rejects once the query is wrapped
+/// in a derived table as `--threads`, `--resume` and the other key-ordered modes do
+pub fn check_derivable(names: &[&str], option: &str) -> Result<(), String> {
+  match names
+    .iter()
+    .enumerate()
+    .find(|(pos, name)| names[..*pos].contains(name))
+  {
+    Some((_, name)) => Err(format!(
+      "{} runs the query as a derived table, which MySQL rejects because column `{}` \
+       appears more than once; give the columns distinct aliases in --sql",
+      option, name
+    )),
+    None => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn renames(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+      .iter()
+      .map(|(old, new)| (old.to_string(), new.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn projects_in_given_order() {
+    let names = ["id", "name", "created_at", "updated_at"];
+    let include = ["*_at".to_string(), "id".to_string()];
+    assert_eq!(project(&names, &include, &[]).unwrap(), vec![2, 3, 0]);
+    let exclude = ["name".to_string()];
+    assert_eq!(project(&names, &[], &exclude).unwrap(), vec![0, 2, 3]);
+    assert!(project(&names, &["missing".to_string()], &[]).is_err());
+  }
+
+  #[test]
+  fn renames_output_columns() {
+    let names = ["id", "name"];
+    assert_eq!(
+      output_names(&names, &[1, 0], &renames(&[("name", "full_name")]), true).unwrap(),
+      vec!["full_name", "id"]
+    );
+    assert!(output_names(&names, &[0], &renames(&[("missing", "x")]), false).is_err());
+  }
+
+  #[test]
+  fn derived_tables_need_unique_names() {
+    assert!(check_derivable(&["id", "name"], "--threads").is_ok());
+    let err = check_derivable(&["id", "name", "id"], "--threads").unwrap_err();
+    assert!(err.starts_with("--threads runs"));
+    assert!(err.contains("`id`"));
+  }
+
+  #[test]
+  fn duplicate_output_names() {
+    let names = ["id", "name", "id"];
+    assert!(output_names(&names, &[0, 1, 2], &[], true).is_err());
+    assert_eq!(
+      output_names(&names, &[0, 1, 2], &[], false).unwrap(),
+      ["id", "name", "id"]
+    );
+    let names = ["id", "name"];
+    assert!(output_names(&names, &[0, 1], &renames(&[("name", "id")]), false).is_err());
+  }
+}
@@ -0,0 +1,208 @@
+use std::{
+  fs::File,
+  io::{self, Write},
+};
+
+use bzip2::write::BzEncoder;
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use xz2::write::XzEncoder;
+
+/// Default levels of the gzip, zstd, bzip2 and xz command line tools
+const GZIP_DEFAULT_LEVEL: u32 = 6;
+const ZSTD_DEFAULT_LEVEL: u32 = 3;
+const BZIP2_DEFAULT_LEVEL: u32 = 9;
+const XZ_DEFAULT_LEVEL: u32 = 6;
+
+/// Streaming compression applied to output files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+  Gzip,
+  Zstd,
+  Bzip2,
+  Xz,
+}
+
+impl Compression {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      Compression::Gzip => "gz",
+      Compression::Zstd => "zst",
+      Compression::Bzip2 => "bz2",
+      Compression::Xz => "xz",
+    }
+  }
+
+  /// Levels the codec accepts
+  fn levels(&self) -> std::ops::RangeInclusive<u32> {
+    match self {
+      Compression::Gzip | Compression::Bzip2 => 1..=9,
+      Compression::Zstd => 1..=22,
+      Compression::Xz => 0..=9,
+    }
+  }
+
+  /// Rejects a level the codec does not accept before anything is exported
+  pub fn check(&self, level: Option<u32>) -> Result<(), String> {
+    if let Some(level) = level {
+      let levels = self.levels();
+      if !levels.contains(&level) {
+        return Err(format!(
+          "{:?} compression levels are {}-{}, got {}",
+          self,
+          levels.start(),
+          levels.end(),
+          level
+        ));
+      }
+    }
+    Ok(())
+  }
+}
+
+/// The file a sink writes to, either directly or through an in-process encoder, so no
+/// uncompressed copy is ever written to disk.
+pub struct Output {
+  inner: Inner,
+  written: u64,
+}
+
+enum Inner {
+  Plain(File),
+  Gzip(GzEncoder<File>),
+  Zstd(zstd::Encoder<'static, File>),
+  Bzip2(BzEncoder<File>),
+  Xz(XzEncoder<File>),
+}
+
+impl Output {
+  pub fn new(file: File, compression: Option<Compression>, level: Option<u32>) -> io::Result<Self> {
+    let (inner, written) = match compression {
+      None => {
+        let len = file.metadata()?.len();
+        (Inner::Plain(file), len)
+      }
+      Some(Compression::Gzip) => {
+        let level = flate2::Compression::new(level.unwrap_or(GZIP_DEFAULT_LEVEL));
+        (Inner::Gzip(GzEncoder::new(file, level)), 0)
+      }
+      Some(Compression::Zstd) => {
+        let level = level.unwrap_or(ZSTD_DEFAULT_LEVEL) as i32;
+        (Inner::Zstd(zstd::Encoder::new(file, level)?), 0)
+      }
+      Some(Compression::Bzip2) => {
+        let level = bzip2::Compression::new(level.unwrap_or(BZIP2_DEFAULT_LEVEL));
+        (Inner::Bzip2(BzEncoder::new(file, level)), 0)
+      }
+      Some(Compression::Xz) => {
+        let level = level.unwrap_or(XZ_DEFAULT_LEVEL);
+        (Inner::Xz(XzEncoder::new(file, level)), 0)
+      }
+    };
+    Ok(Output { inner, written })
+  }
+
+  /// Bytes handed to the file, or to the encoder, so far
+  pub fn offset(&self) -> io::Result<u64> {
+    Ok(self.written)
+  }
+
+  /// Ends the compressed stream and flushes it to the file
+  pub fn finish(&mut self) -> io::Result<()> {
+    match &mut self.inner {
+      Inner::Plain(file) => file.flush(),
+      Inner::Gzip(encoder) => {
+        encoder.try_finish()?;
+        encoder.get_mut().flush()
+      }
+      Inner::Zstd(encoder) => {
+        encoder.do_finish()?;
+        encoder.get_mut().flush()
+      }
+      Inner::Bzip2(encoder) => {
+        encoder.try_finish()?;
+        encoder.get_mut().flush()
+      }
+      Inner::Xz(encoder) => {
+        encoder.try_finish()?;
+        encoder.get_mut().flush()
+      }
+    }
+  }
+
+  fn writer(&mut self) -> &mut dyn Write {
+    match &mut self.inner {
+      Inner::Plain(file) => file,
+      Inner::Gzip(encoder) => encoder,
+      Inner::Zstd(encoder) => encoder,
+      Inner::Bzip2(encoder) => encoder,
+      Inner::Xz(encoder) => encoder,
+    }
+  }
+}
+
+impl Write for Output {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let n = self.writer().write(buf)?;
+    self.written += n as u64;
+    Ok(n)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.writer().flush()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Read;
+
+  use super::*;
+
+  #[test]
+  fn validates_levels() {
+    assert!(Compression::Gzip.check(Some(9)).is_ok());
+    assert!(Compression::Gzip.check(Some(0)).is_err());
+    assert!(Compression::Gzip.check(Some(10)).is_err());
+    assert!(Compression::Zstd.check(Some(22)).is_ok());
+    assert!(Compression::Bzip2.check(Some(0)).is_err());
+    assert!(Compression::Xz.check(Some(0)).is_ok());
+  }
+
+  #[test]
+  fn round_trips_every_codec() {
+    let text: Vec<u8> = (0..5_000)
+      .flat_map(|n| format!("{},row {}\n", n, n % 7).into_bytes())
+      .collect();
+    for compression in [
+      Compression::Gzip,
+      Compression::Zstd,
+      Compression::Bzip2,
+      Compression::Xz,
+    ] {
+      let path = std::env::temp_dir().join(format!(
+        "mysql2csv-{}.{}",
+        std::process::id(),
+        compression.extension()
+      ));
+      let mut out = Output::new(File::create(&path).unwrap(), Some(compression), None).unwrap();
+      out.write_all(&text[..1000]).unwrap();
+      out.flush().unwrap();
+      out.write_all(&text[1000..]).unwrap();
+      assert_eq!(out.offset().unwrap(), text.len() as u64);
+      out.finish().unwrap();
+
+      let file = File::open(&path).unwrap();
+      let mut decoded = Vec::new();
+      match compression {
+        Compression::Gzip => flate2::read::GzDecoder::new(file).read_to_end(&mut decoded),
+        Compression::Zstd => zstd::Decoder::new(file).unwrap().read_to_end(&mut decoded),
+        Compression::Bzip2 => bzip2::read::BzDecoder::new(file).read_to_end(&mut decoded),
+        Compression::Xz => xz2::read::XzDecoder::new(file).read_to_end(&mut decoded),
+      }
+      .unwrap();
+      std::fs::remove_file(&path).unwrap();
+      assert_eq!(decoded, text, "{:?}", compression);
+    }
+  }
+}
@@ -0,0 +1,394 @@
+use std::{collections::HashMap, io::IsTerminal, time::Duration};
+
+use clap::ValueEnum;
+use sqlx::mysql::{MySqlConnectOptions, MySqlPoolOptions, MySqlSslMode};
+use toml_edit::DocumentMut;
+
+use crate::{terminal, Cli};
+
+/// TLS requirement for the server connection, as in the `mysql --ssl-mode` client option
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SslMode {
+  Disabled,
+  Preferred,
+  Required,
+  /// require TLS and check the server certificate against `--ssl-ca`
+  VerifyCa,
+  /// like verify-ca, and also check the host name against the certificate
+  VerifyIdentity,
+}
+
+impl From<SslMode> for MySqlSslMode {
+  fn from(mode: SslMode) -> Self {
+    match mode {
+      SslMode::Disabled => MySqlSslMode::Disabled,
+      SslMode::Preferred => MySqlSslMode::Preferred,
+      SslMode::Required => MySqlSslMode::Required,
+      SslMode::VerifyCa => MySqlSslMode::VerifyCa,
+      SslMode::VerifyIdentity => MySqlSslMode::VerifyIdentity,
+    }
+  }
+}
+
+/// Server address and credentials after merging every source.
+/// Unset fields keep the value of `url`, or the sqlx default without one.
+#[derive(Debug, Clone)]
+pub struct ConnectionParams {
+  pub url: Option<String>,
+  pub host: Option<String>,
+  pub port: Option<u16>,
+  pub socket: Option<String>,
+  pub username: Option<String>,
+  pub password: Option<String>,
+  pub db: String,
+  pub charset: Option<String>,
+  pub collation: Option<String>,
+  pub ssl_mode: Option<SslMode>,
+  pub ssl_ca: Option<String>,
+  pub ssl_cert: Option<String>,
+  pub ssl_key: Option<String>,
+}
+
+impl ConnectionParams {
+  pub fn connect_options(&self) -> Result<MySqlConnectOptions, sqlx::Error> {
+    let mut options = match &self.url {
+      Some(url) => url.parse()?,
+      None => MySqlConnectOptions::new(),
+    };
+    options = options.database(&self.db);
+    if let Some(host) = &self.host {
+      options = options.host(host);
+    }
+    if let Some(port) = self.port {
+      options = options.port(port);
+    }
+    if let Some(socket) = &self.socket {
+      options = options.socket(socket);
+    }
+    if let Some(username) = &self.username {
+      options = options.username(username);
+    }
+    if let Some(password) = &self.password {
+      options = options.password(password);
+    }
+    if let Some(charset) = &self.charset {
+      options = options.charset(charset);
+    }
+    if let Some(collation) = &self.collation {
+      options = options.collation(collation);
+    }
+    if let Some(mode) = self.ssl_mode {
+      options = options.ssl_mode(mode.into());
+    }
+    if let Some(ca) = &self.ssl_ca {
+      options = options.ssl_ca(ca);
+    }
+    if let Some(cert) = &self.ssl_cert {
+      options = options.ssl_client_cert(cert);
+    }
+    if let Some(key) = &self.ssl_key {
+      options = options.ssl_client_key(key);
+    }
+
+    Ok(options)
+  }
+}
+
+/// Pool settings of the command line
+pub fn pool_options(cli: &Cli) -> MySqlPoolOptions {
+  MySqlPoolOptions::new()
+    .max_connections(cli.pool_size)
+    .acquire_timeout(Duration::from_secs(cli.connect_timeout))
+    .idle_timeout((cli.idle_timeout > 0).then(|| Duration::from_secs(cli.idle_timeout)))
+}
+
+/// Reads the first line of a password file, without the line break
+fn read_password_file(path: &str) -> Result<String, Box<dyn std::error::Error>> {
+  let text =
+    std::fs::read_to_string(path).map_err(|err| format!("cannot read {}: {}", path, err))?;
+  Ok(text.lines().next().unwrap_or_default().to_string())
+}
+
+/// Keys of the `[client]` and `[mysql2csv]` groups of an option file such as `~/.my.cnf`,
+/// the latter taking precedence
+fn read_option_file(path: &str) -> HashMap<String, String> {
+  let mut options = HashMap::new();
+  let Ok(text) = std::fs::read_to_string(path) else {
+    return options;
+  };
+
+  let mut in_group = false;
+  for line in text.lines() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with(';') || line.starts_with('!') {
+      continue;
+    }
+    if let Some(group) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+      in_group = matches!(group.trim(), "client" | "mysql2csv");
+      continue;
+    }
+    if !in_group {
+      continue;
+    }
+    let (key, value) = line.split_once('=').unwrap_or((line, ""));
+    let value = value.trim();
+    let value = value
+      .strip_prefix('"')
+      .and_then(|v| v.strip_suffix('"'))
+      .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+      .unwrap_or(value);
+    options.insert(key.trim().replace('-', "_"), value.to_string());
+  }
+
+  options
+}
+
+/// Keys of the `[name]` table of the profile file
+fn read_profile(
+  path: &str,
+  name: &str,
+) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+  let text =
+    std::fs::read_to_string(path).map_err(|err| format!("cannot read {}: {}", path, err))?;
+  let doc: DocumentMut = text.parse()?;
+  let table = doc
+    .get(name)
+    .and_then(|item| item.as_table_like())
+    .ok_or_else(|| format!("profile `{}` not found in {}", name, path))?;
+
+  let mut profile = HashMap::new();
+  for (key, item) in table.iter() {
+    let value = match item.as_value() {
+      Some(toml_edit::Value::String(text)) => text.value().clone(),
+      Some(toml_edit::Value::Integer(num)) => num.value().to_string(),
+      _ => return Err(format!("profile `{}`: `{}` must be a string", name, key).into()),
+    };
+    profile.insert(key.replace('-', "_"), value);
+  }
+
+  Ok(profile)
+}
+
+/// Prompts on the terminal with echo turned off
+fn prompt_password() -> Result<String, Box<dyn std::error::Error>> {
+  if !std::io::stdin().is_terminal() {
+    return Err("--ask-password needs an interactive terminal".into());
+  }
+  Ok(terminal::read_hidden("Enter password: ")?)
+}
+
+/// Merges the connection settings of the command line, `--profile`, environment and `~/.my.cnf`.
+///
+/// Host, port, socket and user take the command line first, then the profile, the environment
+/// (`MYSQL_HOST`, `MYSQL_TCP_PORT`, `MYSQL_UNIX_PORT`, `MYSQL_USER`), the option file and the
+/// built-in default; the database comes from `--db` or the profile. The password is looked up
+/// in `--ask-password`, `--password-file`, the profile, `MYSQL_PWD` and the option file before
+/// falling back to `--password`, which is visible in `ps` and shell history.
+/// With `--url` only the command line and the explicit password sources override the URL.
+pub fn resolve(cli: &Cli) -> Result<ConnectionParams, Box<dyn std::error::Error>> {
+  resolve_with(cli, |name| std::env::var(name).ok())
+}
+
+/// `resolve` reading environment variables, `HOME` included, through `var`
+fn resolve_with(
+  cli: &Cli,
+  var: impl Fn(&str) -> Option<String>,
+) -> Result<ConnectionParams, Box<dyn std::error::Error>> {
+  let env = |name: &str| var(name).filter(|value| !value.is_empty());
+  let home_path = |relative: &str| env("HOME").map(|home| format!("{}/{}", home, relative));
+  let url_options: Option<MySqlConnectOptions> = cli.url.as_deref().map(str::parse).transpose()?;
+
+  let (profile, my_cnf) = if url_options.is_some() {
+    (HashMap::new(), HashMap::new())
+  } else {
+    let profile = match &cli.profile {
+      Some(name) => {
+        let path = match &cli.profile_file {
+          Some(path) => path.clone(),
+          None => home_path(".config/mysql2csv/profiles.toml")
+            .ok_or("HOME is not set, pass --profile-file")?,
+        };
+        read_profile(&path, name)?
+      }
+      None => HashMap::new(),
+    };
+    let my_cnf = home_path(".my.cnf")
+      .map(|path| read_option_file(&path))
+      .unwrap_or_default();
+    (profile, my_cnf)
+  };
+
+  let pick = |flag: &Option<String>, env_name: &str, key: &str, cnf_key: &str| {
+    flag.clone().or_else(|| match url_options {
+      Some(_) => None,
+      None => profile
+        .get(key)
+        .cloned()
+        .or_else(|| env(env_name))
+        .or_else(|| my_cnf.get(cnf_key).cloned()),
+    })
+  };
+
+  let host = pick(&cli.host, "MYSQL_HOST", "host", "host");
+  let port = pick(&cli.port, "MYSQL_TCP_PORT", "port", "port")
+    .map(|port| {
+      port
+        .parse::<u16>()
+        .map_err(|_| format!("invalid port `{}`", port))
+    })
+    .transpose()?;
+  let socket = pick(&cli.socket, "MYSQL_UNIX_PORT", "socket", "socket");
+  let username = pick(&cli.username, "MYSQL_USER", "username", "user");
+  let db = match (cli.db.as_str(), &url_options) {
+    ("", Some(options)) => options
+      .get_database()
+      .map(str::to_string)
+      .ok_or("no database given, pass --db or add it to --url")?,
+    ("", None) => profile
+      .get("db")
+      .cloned()
+      .ok_or("no database given, pass --db or set `db` in the profile")?,
+    (db, _) => db.to_string(),
+  };
+
+  let password = if cli.ask_password {
+    Some(prompt_password()?)
+  } else if let Some(path) = &cli.password_file {
+    Some(read_password_file(path)?)
+  } else if let Some(password) = profile.get("password") {
+    Some(password.clone())
+  } else if let Some(path) = profile.get("password_file") {
+    Some(read_password_file(path)?)
+  } else if let Some(password) = env("MYSQL_PWD") {
+    Some(password)
+  } else if let Some(password) = my_cnf.get("password") {
+    Some(password.clone())
+  } else {
+    cli.password.clone()
+  };
+
+  Ok(ConnectionParams {
+    url: cli.url.clone(),
+    host,
+    port,
+    socket,
+    username,
+    password,
+    db,
+    charset: cli.charset.clone(),
+    collation: cli.collation.clone(),
+    ssl_mode: cli.ssl_mode,
+    ssl_ca: cli.ssl_ca.clone(),
+    ssl_cert: cli.ssl_cert.clone(),
+    ssl_key: cli.ssl_key.clone(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+
+  use super::*;
+
+  /// A HOME holding a `prod` profile and a `.my.cnf`, removed on drop
+  struct Home(std::path::PathBuf);
+
+  impl Home {
+    fn new(name: &str) -> Self {
+      let dir = std::env::temp_dir().join(format!("mysql2csv-{}-{}", std::process::id(), name));
+      std::fs::create_dir_all(dir.join(".config/mysql2csv")).unwrap();
+      std::fs::write(
+        dir.join(".config/mysql2csv/profiles.toml"),
+        "[prod]\nhost = \"profile-host\"\nport = 3307\npassword = \"profile-pwd\"\ndb = \"shop\"\n",
+      )
+      .unwrap();
+      std::fs::write(
+        dir.join(".my.cnf"),
+        "[client]\nhost=cnf-host\nuser=cnf-user\nsocket=/cnf.sock\npassword=cnf-pwd\n",
+      )
+      .unwrap();
+      Home(dir)
+    }
+
+    fn resolve(&self, args: &[&str], vars: &[(&str, &str)]) -> ConnectionParams {
+      let cli = Cli::parse_from(["mysql2csv"].iter().chain(args));
+      let home = self.0.to_string_lossy().to_string();
+      resolve_with(&cli, |name| match name {
+        "HOME" => Some(home.clone()),
+        _ => vars
+          .iter()
+          .find(|(var, _)| *var == name)
+          .map(|(_, value)| value.to_string()),
+      })
+      .unwrap()
+    }
+  }
+
+  impl Drop for Home {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_dir_all(&self.0);
+    }
+  }
+
+  #[test]
+  fn flags_then_profile_then_env_then_option_file() {
+    let home = Home::new("resolve");
+    let vars = [
+      ("MYSQL_HOST", "env-host"),
+      ("MYSQL_TCP_PORT", "3308"),
+      ("MYSQL_USER", "env-user"),
+      ("MYSQL_PWD", "env-pwd"),
+    ];
+
+    let params = home.resolve(&["--profile", "prod"], &vars);
+    assert_eq!(params.host.as_deref(), Some("profile-host"));
+    assert_eq!(params.port, Some(3307));
+    assert_eq!(params.username.as_deref(), Some("env-user"));
+    assert_eq!(params.socket.as_deref(), Some("/cnf.sock"));
+    assert_eq!(params.password.as_deref(), Some("profile-pwd"));
+    assert_eq!(params.db, "shop");
+
+    let params = home.resolve(
+      &["--profile", "prod", "-H", "flag-host", "-D", "other"],
+      &vars,
+    );
+    assert_eq!(params.host.as_deref(), Some("flag-host"));
+    assert_eq!(params.db, "other");
+
+    let params = home.resolve(&["-D", "shop"], &vars);
+    assert_eq!(params.host.as_deref(), Some("env-host"));
+    assert_eq!(params.port, Some(3308));
+    assert_eq!(params.password.as_deref(), Some("env-pwd"));
+
+    let params = home.resolve(&["-D", "shop", "--password", "flag-pwd"], &[]);
+    assert_eq!(params.host.as_deref(), Some("cnf-host"));
+    assert_eq!(params.username.as_deref(), Some("cnf-user"));
+    assert_eq!(params.password.as_deref(), Some("cnf-pwd"));
+  }
+
+  #[test]
+  fn url_ignores_environment_and_files() {
+    let home = Home::new("resolve-url");
+    let params = home.resolve(
+      &["--url", "mysql://u@db:3306/shop"],
+      &[("MYSQL_HOST", "env-host")],
+    );
+    assert_eq!(params.host, None);
+    assert_eq!(params.password, None);
+    assert_eq!(params.db, "shop");
+  }
+
+  #[test]
+  fn reads_option_file_groups() {
+    let home = Home::new("option-file");
+    let path = home.0.join("extra.cnf");
+    std::fs::write(
+      &path,
+      "# comment\n[mysqld]\nport=1\n[client]\nport = 2\nssl-ca = \"/ca.pem\"\n[mysql2csv]\nport=3\n",
+    )
+    .unwrap();
+    let options = read_option_file(&path.to_string_lossy());
+    assert_eq!(options["port"], "3");
+    assert_eq!(options["ssl_ca"], "/ca.pem");
+  }
+}
@@ -0,0 +1,215 @@
+use futures::TryStreamExt;
+use indicatif::ProgressBar;
+use log::warn;
+use sqlx::MySqlPool;
+
+use crate::{
+  checkpoint::{resume_query, Checkpointer},
+  incremental::Watermark,
+  retry::RetryPolicy,
+  sink::Sink,
+  transform::Pipeline,
+  value::{key_literal, ColumnKind, Value},
+  Cli,
+};
+
+/// Everything needed to turn query results into output rows
+pub struct Export<'a> {
+  pub pool: &'a MySqlPool,
+  pub cli: &'a Cli,
+  /// result columns of the query, which `--index`, `--transform` and `--mask` refer to
+  pub query_col_name: &'a [&'a str],
+  pub query_col_type: &'a [ColumnKind],
+  /// positions of the written columns within the query columns
+  pub projection: &'a [usize],
+  /// headers of the written columns after `--columns`, `--exclude-columns` and `--rename`
+  pub vec_col_name: &'a [&'a str],
+  /// MySQL type names as reported by the server, e.g. `BIGINT UNSIGNED`, `DECIMAL(p,s)` when
+  /// `--table` declares the column, and `VARCHAR` for columns the pipeline rewrites
+  pub vec_col_type_name: &'a [String],
+  /// `--transform` and `--mask` steps applied to the written copy of each decoded value
+  pub pipeline: &'a Pipeline,
+  pub pb: &'a ProgressBar,
+}
+
+impl Export<'_> {
+  /// Streams the rows returned by `sql` into `sink`, completes it and returns how many rows were written.
+  ///
+  /// Transient errors re-issue the query under the `--retries` policy. When `sql` is ordered by
+  /// the `key` column the new query continues after the last written key, otherwise it can
+  /// only be retried before the first row.
+  pub async fn write_rows(
+    &self,
+    sql: &str,
+    key: Option<&str>,
+    mut sink: Box<dyn Sink + '_>,
+    mut checkpoint: Option<&mut Checkpointer>,
+    mut watermark: Option<&mut Watermark>,
+  ) -> Result<u64, Box<dyn std::error::Error>> {
+    let cli = self.cli;
+    let policy = RetryPolicy::new(cli);
+    let key_pos = key.and_then(|key| self.query_col_name.iter().position(|name| *name == key));
+    let mut query = sql.to_string();
+    let mut stream = sqlx::query(&query).fetch(self.pool);
+    let mut attempt = 0;
+    let mut last_key: Option<String> = None;
+    let mut written = 0;
+    let mut values = Vec::with_capacity(self.query_col_type.len());
+    let mut projected = Vec::with_capacity(self.projection.len());
+
+    loop {
+      let row = match stream.try_next().await {
+        Ok(Some(row)) => row,
+        Ok(None) => break,
+        Err(err) if attempt < policy.attempts() && policy.is_transient(&err) => {
+          let retry_sql = match (key, key_pos, last_key.as_deref()) {
+            (_, _, None) if written == 0 => sql.to_string(),
+            (Some(key), Some(_), Some(last)) => resume_query(sql, key, Some(last)),
+            _ => return Err(err.into()),
+          };
+          attempt += 1;
+          let delay = policy.delay(attempt);
+          warn!(
+            "{} after {} rows, retry {}/{} in {:?}",
+            err,
+            written,
+            attempt,
+            policy.attempts(),
+            delay
+          );
+          drop(stream);
+          tokio::time::sleep(delay).await;
+          query = retry_sql;
+          stream = sqlx::query(&query).fetch(self.pool);
+          continue;
+        }
+        Err(err) => return Err(err.into()),
+      };
+      attempt = 0;
+
+      values.clear();
+      for (num, kind) in self.query_col_type.iter().enumerate() {
+        values.push(Value::decode(&row, num, *kind)?);
+      }
+      // keys and watermarks are tracked on the decoded values, only the written copy is transformed
+      projected.clear();
+      projected.extend(
+        self
+          .projection
+          .iter()
+          .map(|pos| self.pipeline.apply(*pos, values[*pos].clone())),
+      );
+      sink.write_row(&projected)?;
+      if let Some(cp) = checkpoint.as_deref_mut() {
+        cp.observe(&values[cp.index_pos()], sink.as_mut())?;
+      }
+      if let Some(watermark) = watermark.as_deref_mut() {
+        watermark.observe(&values[watermark.pos()]);
+      }
+      if let Some(literal) = key_pos.and_then(|pos| key_literal(&values[pos])) {
+        last_key = Some(literal);
+      }
+      written += 1;
+      self.pb.inc(1);
+    }
+    sink.finish()?;
+
+    Ok(written)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+  };
+
+  use clap::Parser;
+  use sqlx::mysql::MySqlPoolOptions;
+
+  use super::*;
+
+  /// Records whether it was finished, shared with the test after the export consumed the sink
+  #[derive(Default, Clone)]
+  struct Recorder(Arc<Mutex<bool>>);
+
+  impl Sink for Recorder {
+    fn write_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+      Ok(())
+    }
+
+    fn write_row(&mut self, _row: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+      Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+      Ok(())
+    }
+
+    fn offset(&self) -> std::io::Result<u64> {
+      Ok(0)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+      *self.0.lock().unwrap() = true;
+      Ok(())
+    }
+  }
+
+  /// Runs a query against a server that refuses connections, returning the error,
+  /// how long it took and whether the sink was finished
+  async fn failing_export(args: &[&str]) -> (String, Duration, bool) {
+    let cli = Cli::parse_from(["mysql2csv", "-D", "shop"].iter().chain(args));
+    let pool = MySqlPoolOptions::new()
+      .acquire_timeout(Duration::from_millis(100))
+      .connect_lazy("mysql://root@127.0.0.1:1/shop")
+      .unwrap();
+    let pipeline = Pipeline::new(&[], "", &[], None, &[]).unwrap();
+    let pb = ProgressBar::hidden();
+    let export = Export {
+      pool: &pool,
+      cli: &cli,
+      query_col_name: &["id"],
+      query_col_type: &[ColumnKind::Int],
+      projection: &[0],
+      vec_col_name: &["id"],
+      vec_col_type_name: &["BIGINT".to_string()],
+      pipeline: &pipeline,
+      pb: &pb,
+    };
+    let recorder = Recorder::default();
+
+    let started = Instant::now();
+    let err = export
+      .write_rows(
+        "SELECT id FROM orders ORDER BY id",
+        Some("id"),
+        Box::new(recorder.clone()),
+        None,
+        None,
+      )
+      .await
+      .unwrap_err();
+    let finished = *recorder.0.lock().unwrap();
+    (err.to_string(), started.elapsed(), finished)
+  }
+
+  #[tokio::test]
+  async fn gives_up_after_the_retries() {
+    let (err, elapsed, finished) =
+      failing_export(&["--retries", "2", "--retry-backoff-ms", "200"]).await;
+    assert!(err.contains("timed out"), "{}", err);
+    // two backoffs of 200ms and 400ms between the three attempts
+    assert!(elapsed >= Duration::from_millis(600));
+    assert!(!finished);
+  }
+
+  #[tokio::test]
+  async fn fails_at_once_without_retries() {
+    let (_, elapsed, finished) =
+      failing_export(&["--retries", "0", "--retry-backoff-ms", "5000"]).await;
+    assert!(elapsed < Duration::from_secs(5));
+    assert!(!finished);
+  }
+}
@@ -0,0 +1,150 @@
+use chrono::Local;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  export::Export,
+  progress, sink, state,
+  value::{key_literal, Value},
+};
+
+/// High-water mark of the last successful incremental export, saved as `{table}.state.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct State {
+  /// watermark column the value belongs to
+  pub column: String,
+  /// greatest watermark exported so far, as an SQL literal
+  pub watermark: Option<String>,
+  /// local time of the export that produced this state
+  pub exported_at: String,
+}
+
+/// Tracks the watermark column while rows are written; the query is ordered by it,
+/// so the last non-NULL value seen is the new high-water mark
+pub struct Watermark {
+  pos: usize,
+  last: Option<Value>,
+}
+
+impl Watermark {
+  pub fn new(pos: usize) -> Self {
+    Watermark { pos, last: None }
+  }
+
+  pub fn pos(&self) -> usize {
+    self.pos
+  }
+
+  pub fn observe(&mut self, value: &Value) {
+    if !matches!(value, Value::Null) {
+      self.last = Some(value.clone());
+    }
+  }
+}
+
+/// Wraps the query as a derived table ordered by the watermark column,
+/// keeping only rows past `watermark` when there is one
+fn watermark_query(sql: &str, column: &str, watermark: Option<&str>) -> String {
+  let sql = sql.trim().trim_end_matches(';');
+  match watermark {
+    Some(literal) => format!(
+      "SELECT * FROM ({}) t WHERE t.`{}` > {} ORDER BY t.`{}`",
+      sql, column, literal, column
+    ),
+    None => format!("SELECT * FROM ({}) t ORDER BY t.`{}`", sql, column),
+  }
+}
+
+/// Exports only rows whose `--incremental` column is past the stored watermark
+/// into a new `{table}_{timestamp}` file, then advances the watermark
+pub async fn export_incremental(
+  export: &Export<'_>,
+  column: &str,
+  folder_path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let cli = export.cli;
+  let pos = export
+    .query_col_name
+    .iter()
+    .position(|name| *name == column)
+    .ok_or_else(|| format!("watermark column `{}` is not in the query result", column))?;
+
+  let state_path = format!("{}/{}.state.json", folder_path, cli.table);
+  let previous = state::load::<State>(&state_path)?.filter(|state| state.column == column);
+  let watermark = previous
+    .as_ref()
+    .and_then(|state| state.watermark.as_deref());
+  match watermark {
+    Some(literal) => info!("Exporting rows with {} > {}", column, literal),
+    None => info!("No previous watermark for {}, exporting all rows", column),
+  }
+  let sql = watermark_query(&cli.sql, column, watermark);
+
+  progress::set_total(export, &sql).await?;
+
+  let now = Local::now();
+  let stem = format!("{}_{}", cli.table, now.format("%Y%m%d%H%M%S"));
+  let mut sink = sink::open(export, folder_path, &stem)?;
+  sink.write_header()?;
+
+  let mut watermark = Watermark::new(pos);
+  let written = export
+    .write_rows(&sql, None, sink, None, Some(&mut watermark))
+    .await?;
+
+  let state = match watermark.last {
+    Some(value) => State {
+      column: column.to_string(),
+      watermark: key_literal(&value),
+      exported_at: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+    },
+    None => previous.unwrap_or_else(|| State {
+      column: column.to_string(),
+      ..Default::default()
+    }),
+  };
+  state::save(&state, &state_path)?;
+  info!(
+    "Wrote {} rows to {}, watermark is now {}",
+    written,
+    stem,
+    state.watermark.as_deref().unwrap_or("unset")
+  );
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn queries_rows_past_the_watermark() {
+    assert_eq!(
+      watermark_query("SELECT * FROM orders;\n", "updated_at", None),
+      "SELECT * FROM (SELECT * FROM orders) t ORDER BY t.`updated_at`"
+    );
+    assert_eq!(
+      watermark_query(
+        "SELECT * FROM orders",
+        "updated_at",
+        Some("'2024-01-02 03:04:05'")
+      ),
+      "SELECT * FROM (SELECT * FROM orders) t WHERE t.`updated_at` > '2024-01-02 03:04:05' \
+       ORDER BY t.`updated_at`"
+    );
+  }
+
+  #[test]
+  fn keeps_the_last_non_null_value() {
+    let mut watermark = Watermark::new(1);
+    watermark.observe(&Value::Int(3));
+    watermark.observe(&Value::Int(7));
+    watermark.observe(&Value::Null);
+    assert_eq!(watermark.pos(), 1);
+    assert_eq!(
+      watermark.last.as_ref().and_then(key_literal).as_deref(),
+      Some("7")
+    );
+  }
+}
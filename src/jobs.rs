@@ -0,0 +1,281 @@
+use std::path::Path;
+
+use clap::Parser;
+use futures::{stream, StreamExt};
+use log::{error, info};
+use toml_edit::{DocumentMut, Item, Value};
+
+use crate::{run, Cli};
+
+/// One export of a job file, parsed into the same options as a command line
+struct Job {
+  name: String,
+  cli: Cli,
+}
+
+/// Renders a TOML value as a command line option value; nested arrays become comma separated lists
+fn option_value(value: &Value) -> Result<String, String> {
+  match value {
+    Value::String(text) => Ok(text.value().clone()),
+    Value::Integer(num) => Ok(num.value().to_string()),
+    Value::Float(num) => Ok(num.value().to_string()),
+    Value::Array(items) => items
+      .iter()
+      .map(option_value)
+      .collect::<Result<Vec<_>, _>>()
+      .map(|items| items.join(",")),
+    other => Err(format!("{} values are not supported", other.type_name())),
+  }
+}
+
+/// Adds the keys of a `[defaults]` or `[[job]]` table to `options`, replacing earlier ones.
+/// Keys are long option names, `sql_file` is read relative to the job file.
+fn collect_options(
+  table: &dyn toml_edit::TableLike,
+  base_dir: &Path,
+  options: &mut Vec<(String, Option<String>)>,
+) -> Result<(), String> {
+  for (key, item) in table.iter() {
+    let value = item
+      .as_value()
+      .ok_or_else(|| format!("`{}` must be a value", key))?;
+    let (key, value) = match (key, value) {
+      ("sql_file" | "sql-file", Value::String(path)) => {
+        let path = base_dir.join(path.value());
+        let sql = std::fs::read_to_string(&path)
+          .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+        ("sql".to_string(), Some(sql))
+      }
+      (key, Value::Boolean(flag)) => {
+        let key = key.replace('_', "-");
+        options.retain(|(name, _)| *name != key);
+        if *flag.value() {
+          options.push((key, None));
+        }
+        continue;
+      }
+      (key, Value::Array(items)) => {
+        // one option per item, so repeatable options like `transform` keep commas in values
+        let key = key.replace('_', "-");
+        options.retain(|(name, _)| *name != key);
+        for item in items.iter() {
+          let item = option_value(item).map_err(|err| format!("`{}`: {}", key, err))?;
+          options.push((key.clone(), Some(item)));
+        }
+        continue;
+      }
+      (key, value) => (
+        key.replace('_', "-"),
+        Some(option_value(value).map_err(|err| format!("`{}`: {}", key, err))?),
+      ),
+    };
+    options.retain(|(name, _)| *name != key);
+    options.push((key, value));
+  }
+
+  Ok(())
+}
+
+/// Parses a job file into its concurrency and jobs, validating every job up front
+fn load(path: &str) -> Result<(usize, Vec<Job>), Box<dyn std::error::Error>> {
+  let text = std::fs::read_to_string(path)?;
+  let doc: DocumentMut = text.parse()?;
+  let base_dir = Path::new(path).parent().unwrap_or(Path::new("."));
+
+  let concurrency = match doc.get("concurrency") {
+    None => 1,
+    Some(item) => item
+      .as_integer()
+      .filter(|num| *num > 0)
+      .ok_or("`concurrency` must be a positive integer")? as usize,
+  };
+
+  let mut defaults = Vec::new();
+  if let Some(item) = doc.get("defaults") {
+    let table = item.as_table_like().ok_or("`defaults` must be a table")?;
+    collect_options(table, base_dir, &mut defaults).map_err(|err| format!("defaults: {}", err))?;
+  }
+
+  let tables = match doc.get("job") {
+    Some(Item::ArrayOfTables(tables)) => tables,
+    _ => return Err(format!("{} has no [[job]] entries", path).into()),
+  };
+
+  let mut jobs = Vec::new();
+  let mut errors = Vec::new();
+  for (num, table) in tables.iter().enumerate() {
+    let name = table
+      .get("name")
+      .and_then(|item| item.as_str())
+      .map(str::to_string);
+    let mut table = table.clone();
+    table.remove("name");
+
+    let mut options = defaults.clone();
+    let parsed = collect_options(&table, base_dir, &mut options).and_then(|()| {
+      let args = options.into_iter().map(|(key, value)| match value {
+        Some(value) => format!("--{}={}", key, value),
+        None => format!("--{}", key),
+      });
+      Cli::try_parse_from(std::iter::once("mysql2csv".to_string()).chain(args)).map_err(|err| {
+        let text = err.to_string();
+        let first = text.lines().next().unwrap_or_default();
+        first.trim_start_matches("error: ").to_string()
+      })
+    });
+    let label = name.unwrap_or_else(|| match &parsed {
+      Ok(cli) if !cli.table.is_empty() => cli.table.clone(),
+      _ => format!("job {}", num + 1),
+    });
+    match parsed {
+      Ok(cli) if cli.job.is_some() => errors.push(format!("{}: jobs cannot nest --job", label)),
+      Ok(cli) => jobs.push(Job { name: label, cli }),
+      Err(err) => errors.push(format!("{}: {}", label, err)),
+    }
+  }
+  if !errors.is_empty() {
+    return Err(errors.join("\n").into());
+  }
+
+  Ok((concurrency, jobs))
+}
+
+/// Runs every job of the `--job` file, `concurrency` at a time, and fails if any job failed
+pub async fn run_jobs(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+  let (concurrency, jobs) = load(path)?;
+  let total = jobs.len();
+  info!(
+    "Running {} jobs from {}, {} at a time",
+    total, path, concurrency
+  );
+
+  let failed: Vec<String> = stream::iter(jobs)
+    .map(|job| async move {
+      info!("Job {} started", job.name);
+      match run(job.cli).await {
+        Ok(()) => {
+          info!("Job {} succeeded", job.name);
+          None
+        }
+        Err(err) => {
+          error!("Job {} failed: {}", job.name, err);
+          Some(job.name)
+        }
+      }
+    })
+    .buffer_unordered(concurrency)
+    .filter_map(|name| async move { name })
+    .collect()
+    .await;
+
+  info!("{} of {} jobs succeeded", total - failed.len(), total);
+  if !failed.is_empty() {
+    return Err(format!("failed jobs: {}", failed.join(", ")).into());
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Loads `text` as a job file next to an `orders.sql` query file
+  fn load_text(name: &str, text: &str) -> Result<(usize, Vec<Job>), String> {
+    let dir = std::env::temp_dir().join(format!("mysql2csv-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("orders.sql"), "SELECT * FROM orders").unwrap();
+    let path = dir.join("jobs.toml");
+    std::fs::write(&path, text).unwrap();
+    let loaded = load(&path.to_string_lossy()).map_err(|err| err.to_string());
+    std::fs::remove_dir_all(&dir).unwrap();
+    loaded
+  }
+
+  #[test]
+  fn jobs_override_defaults() {
+    let (concurrency, jobs) = load_text(
+      "jobs-defaults",
+      r#"
+concurrency = 2
+
+[defaults]
+db = "shop"
+delim = ","
+columns = ["id", "total"]
+resume = true
+
+[[job]]
+name = "all orders"
+table = "orders"
+sql_file = "orders.sql"
+transform = ["note:replace=/,/;/", "note:trim"]
+
+[[job]]
+table = "users"
+delim = "|"
+resume = false
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(concurrency, 2);
+    let names: Vec<&str> = jobs.iter().map(|job| job.name.as_str()).collect();
+    assert_eq!(names, ["all orders", "users"]);
+
+    let orders = &jobs[0].cli;
+    assert_eq!(orders.db, "shop");
+    assert_eq!(orders.sql, "SELECT * FROM orders");
+    assert_eq!(orders.delim, ",");
+    assert_eq!(orders.columns, ["id", "total"]);
+    assert!(orders.resume);
+    assert_eq!(orders.transform.len(), 2);
+
+    let users = &jobs[1].cli;
+    assert_eq!(users.table, "users");
+    assert_eq!(users.delim, "|");
+    assert!(!users.resume);
+  }
+
+  #[test]
+  fn reports_every_invalid_job() {
+    let err = load_text(
+      "jobs-invalid",
+      r#"
+[defaults]
+db = "shop"
+
+[[job]]
+table = "orders"
+threads = "many"
+
+[[job]]
+table = "users"
+
+[[job]]
+job = "other.toml"
+"#,
+    )
+    .err()
+    .unwrap();
+
+    let lines: Vec<&str> = err.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("job 1: invalid value 'many'"));
+    assert!(lines[1].starts_with("job 3: ") && lines[1].contains("--job"));
+  }
+
+  #[test]
+  fn needs_jobs_and_a_positive_concurrency() {
+    assert!(load_text("jobs-none", "concurrency = 1\n")
+      .err()
+      .unwrap()
+      .ends_with("has no [[job]] entries"));
+    assert_eq!(
+      load_text("jobs-zero", "concurrency = 0\n[[job]]\ntable = \"t\"\n")
+        .err()
+        .unwrap(),
+      "`concurrency` must be a positive integer"
+    );
+  }
+}
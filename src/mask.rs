@@ -0,0 +1,267 @@
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use sha2::Sha256;
+
+use crate::{tables::glob_regex, value::Value, Cli};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Environment variable holding the masking key when `--mask-key-file` is not given
+pub const MASK_KEY_ENV: &str = "MYSQL2CSV_MASK_KEY";
+
+/// How a masked column is written; NULL stays NULL under every rule
+#[derive(Debug, Clone)]
+pub enum MaskRule {
+  /// replace the whole value with this text
+  Redact(String),
+  /// keep the last N characters and star out the rest, e.g. `****1234`; values of N
+  /// characters or fewer still lose at least their first one
+  Partial(usize),
+  /// hex HMAC-SHA256 of the value, equal inputs give equal pseudonyms across tables and runs
+  Hash,
+  /// keyed random digits and letters in place of the original ones, punctuation kept
+  Fake,
+}
+
+impl MaskRule {
+  fn needs_key(&self) -> bool {
+    matches!(self, MaskRule::Hash | MaskRule::Fake)
+  }
+}
+
+/// `--mask PATTERN:RULE`, the pattern is a column name glob
+#[derive(Debug, Clone)]
+pub struct Mask {
+  pub pattern: Regex,
+  pub rule: MaskRule,
+}
+
+/// Parses a `--mask` value such as `email:hash`, `*_phone:partial=4` or `ssn:redact=XXX`
+pub fn parse_mask(spec: &str) -> Result<Mask, String> {
+  let (pattern, rule) = spec
+    .rsplit_once(':')
+    .ok_or_else(|| format!("expected PATTERN:RULE, got `{}`", spec))?;
+  let (name, arg) = match rule.split_once('=') {
+    Some((name, arg)) => (name, Some(arg)),
+    None => (rule, None),
+  };
+  let rule = match (name, arg) {
+    ("redact", None) => MaskRule::Redact("***".to_string()),
+    ("redact", Some(text)) => MaskRule::Redact(text.to_string()),
+    ("partial", None) => MaskRule::Partial(4),
+    ("partial", Some(keep)) => MaskRule::Partial(
+      keep
+        .parse()
+        .map_err(|_| format!("partial needs a character count, got `{}`", keep))?,
+    ),
+    ("hash", None) => MaskRule::Hash,
+    ("fake", None) => MaskRule::Fake,
+    _ => return Err(format!("unknown mask rule `{}`", rule)),
+  };
+
+  Ok(Mask {
+    pattern: glob_regex(pattern).map_err(|err| err.to_string())?,
+    rule,
+  })
+}
+
+/// Reads the HMAC key from `--mask-key-file`, or from `MYSQL2CSV_MASK_KEY`.
+/// Only needed when a `hash` or `fake` rule is used.
+pub fn load_key(cli: &Cli) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+  if !cli.mask.iter().any(|mask| mask.rule.needs_key()) {
+    return Ok(None);
+  }
+  let key = match &cli.mask_key_file {
+    Some(path) => std::fs::read_to_string(path)
+      .map_err(|err| format!("cannot read {}: {}", path, err))?
+      .trim_end_matches(['\r', '\n'])
+      .to_string(),
+    None => std::env::var(MASK_KEY_ENV).map_err(|_| {
+      format!(
+        "hash and fake masks need a key, pass --mask-key-file or set {}",
+        MASK_KEY_ENV
+      )
+    })?,
+  };
+  if key.is_empty() {
+    return Err("the masking key is empty".into());
+  }
+
+  Ok(Some(key.into_bytes()))
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+  let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any length");
+  for part in parts {
+    mac.update(part);
+  }
+  mac.finalize().into_bytes().into()
+}
+
+/// Replaces each digit and letter with an ASCII one drawn from a keystream seeded by the value,
+/// so the same input always gets the same fake and its length and punctuation survive
+fn fake(key: &[u8], text: &str) -> String {
+  let mut block = 0u32;
+  let mut stream = Vec::new();
+  let mut out = String::with_capacity(text.len());
+  for ch in text.chars() {
+    if !ch.is_alphanumeric() {
+      out.push(ch);
+      continue;
+    }
+    if stream.is_empty() {
+      stream = hmac(key, &[b"fake", &block.to_be_bytes(), text.as_bytes()]).to_vec();
+      block += 1;
+    }
+    let byte = stream.pop().unwrap_or_default();
+    out.push(match ch {
+      '0'..='9' => (b'0' + byte % 10) as char,
+      'A'..='Z' => (b'A' + byte % 26) as char,
+      _ => (b'a' + byte % 26) as char,
+    });
+  }
+  out
+}
+
+/// Applies a rule to a decoded value, `key` is set whenever the rule needs one
+pub fn apply(rule: &MaskRule, key: Option<&[u8]>, value: Value) -> Value {
+  let text = match value {
+    Value::Null => return Value::Null,
+    Value::Text(text) => text,
+    value => value.render().unwrap_or_default(),
+  };
+  Value::Text(match (rule, key) {
+    (MaskRule::Redact(replacement), _) => replacement.clone(),
+    (MaskRule::Partial(keep), _) => {
+      let len = text.chars().count();
+      let hidden = len.saturating_sub(*keep).max(len.min(1));
+      "*".repeat(hidden) + &text.chars().skip(hidden).collect::<String>()
+    }
+    (MaskRule::Hash, Some(key)) => hmac(key, &[text.as_bytes()])
+      .iter()
+      .map(|b| format!("{:02x}", b))
+      .collect(),
+    (MaskRule::Fake, Some(key)) => fake(key, &text),
+    // load_key guarantees a key for these rules
+    (MaskRule::Hash | MaskRule::Fake, None) => "***".to_string(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const KEY: &[u8] = b"secret";
+
+  fn text(value: Value) -> String {
+    match value {
+      Value::Text(text) => text,
+      value => panic!("expected text, got {:?}", value),
+    }
+  }
+
+  #[test]
+  fn parses_rules() {
+    let mask = parse_mask("*_phone:partial=2").unwrap();
+    assert!(matches!(mask.rule, MaskRule::Partial(2)));
+    assert!(mask.pattern.is_match("home_phone"));
+    assert!(!mask.pattern.is_match("phone_type"));
+    assert!(matches!(
+      parse_mask("card:partial").unwrap().rule,
+      MaskRule::Partial(4)
+    ));
+    assert!(
+      matches!(parse_mask("ssn:redact").unwrap().rule, MaskRule::Redact(text) if text == "***")
+    );
+    assert!(
+      matches!(parse_mask("ssn:redact=N/A").unwrap().rule, MaskRule::Redact(text) if text == "N/A")
+    );
+    assert!(matches!(
+      parse_mask("email:hash").unwrap().rule,
+      MaskRule::Hash
+    ));
+    assert!(matches!(
+      parse_mask("name:fake").unwrap().rule,
+      MaskRule::Fake
+    ));
+  }
+
+  #[test]
+  fn rejects_bad_rules() {
+    assert!(parse_mask("email").is_err());
+    assert!(parse_mask("email:scramble").is_err());
+    assert!(parse_mask("email:hash=1").is_err());
+    assert!(parse_mask("card:partial=four").is_err());
+  }
+
+  #[test]
+  fn applies_rules() {
+    let key = Some(KEY);
+    assert_eq!(apply(&MaskRule::Hash, key, Value::Null), Value::Null);
+    assert_eq!(
+      text(apply(
+        &MaskRule::Partial(4),
+        None,
+        Value::Text("4111222233334444".into())
+      )),
+      "************4444"
+    );
+    assert_eq!(
+      text(apply(&MaskRule::Partial(4), None, Value::Int(12))),
+      "*2"
+    );
+    assert_eq!(
+      text(apply(
+        &MaskRule::Partial(4),
+        None,
+        Value::Text("1234".into())
+      )),
+      "*234"
+    );
+    assert_eq!(
+      text(apply(
+        &MaskRule::Partial(4),
+        None,
+        Value::Text(String::new())
+      )),
+      ""
+    );
+    assert_eq!(
+      text(apply(&MaskRule::Redact("X".into()), None, Value::Int(7))),
+      "X"
+    );
+
+    let hash = text(apply(&MaskRule::Hash, key, Value::Text("a@b.c".into())));
+    assert_eq!(hash.len(), 64);
+    assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(
+      text(apply(&MaskRule::Hash, key, Value::Text("a@b.c".into()))),
+      hash
+    );
+    assert_ne!(
+      text(apply(
+        &MaskRule::Hash,
+        Some(b"other"),
+        Value::Text("a@b.c".into())
+      )),
+      hash
+    );
+  }
+
+  #[test]
+  fn fakes_keep_shape_and_are_deterministic() {
+    let input = "Jane Doe, +1 (555) 010-9999";
+    let faked = fake(KEY, input);
+    assert_eq!(faked, fake(KEY, input));
+    assert_ne!(faked, fake(b"other", input));
+    assert_eq!(faked.chars().count(), input.chars().count());
+    for (original, masked) in input.chars().zip(faked.chars()) {
+      match original {
+        '0'..='9' => assert!(masked.is_ascii_digit()),
+        'A'..='Z' => assert!(masked.is_ascii_uppercase()),
+        'a'..='z' => assert!(masked.is_ascii_lowercase()),
+        _ => assert_eq!(masked, original),
+      }
+    }
+  }
+}
@@ -0,0 +1,173 @@
+use std::{
+  fs::{File, OpenOptions},
+  io,
+};
+
+use futures::future::try_join_all;
+use log::info;
+
+use crate::{export::Export, sink, value::ColumnKind};
+
+/// Splits the inclusive key range `[min, max]` into at most `parts` contiguous inclusive ranges.
+/// Keys are `i128` so both BIGINT and BIGINT UNSIGNED bounds fit.
+pub fn split_ranges(min: i128, max: i128, parts: usize) -> Vec<(i128, i128)> {
+  if max < min || parts == 0 {
+    return Vec::new();
+  }
+  let span = max - min + 1;
+  let parts = (parts as i128).min(span);
+  let step = span / parts;
+  let extra = span % parts;
+
+  let mut ranges = Vec::with_capacity(parts as usize);
+  let mut lo = min;
+  for i in 0..parts {
+    let len = step + i128::from(i < extra);
+    ranges.push((lo, lo + len - 1));
+    lo += len;
+  }
+
+  ranges
+}
+
+/// Wraps the user query so only keys within `[lo, hi]` are returned, in key order
+pub fn range_query(sql: &str, index: &str, lo: i128, hi: i128) -> String {
+  format!(
+    "SELECT * FROM ({}) t WHERE t.`{}` BETWEEN {} AND {} ORDER BY t.`{}`",
+    sql.trim().trim_end_matches(';'),
+    index,
+    lo,
+    hi,
+    index
+  )
+}
+
+/// Exports the query with `cli.threads` concurrent range queries over the `--index` key,
+/// which must be an integer column of kind `kind`.
+/// Writes one part file per range, or a single ordered `{table}` file when `--merge` is set.
+pub async fn export_ranges(
+  export: &Export<'_>,
+  index: &str,
+  kind: ColumnKind,
+  folder_path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let cli = export.cli;
+  if cli.merge && !cli.format.appendable() {
+    return Err(format!("--merge is not supported for {:?} output", cli.format).into());
+  }
+  let sql = cli.sql.trim().trim_end_matches(';');
+  let (min, max): (Option<i128>, Option<i128>) = if kind == ColumnKind::UInt {
+    let bounds_query = format!(
+      "SELECT CAST(MIN(t.`{}`) AS UNSIGNED), CAST(MAX(t.`{}`) AS UNSIGNED) FROM ({}) t",
+      index, index, sql
+    );
+    let (min, max): (Option<u64>, Option<u64>) =
+      sqlx::query_as(&bounds_query).fetch_one(export.pool).await?;
+    (min.map(i128::from), max.map(i128::from))
+  } else {
+    let bounds_query = format!(
+      "SELECT CAST(MIN(t.`{}`) AS SIGNED), CAST(MAX(t.`{}`) AS SIGNED) FROM ({}) t",
+      index, index, sql
+    );
+    let (min, max): (Option<i64>, Option<i64>) =
+      sqlx::query_as(&bounds_query).fetch_one(export.pool).await?;
+    (min.map(i128::from), max.map(i128::from))
+  };
+  let ranges = match (min, max) {
+    (Some(min), Some(max)) => split_ranges(min, max, cli.threads),
+    _ => Vec::new(),
+  };
+  info!(
+    "Splitting {} on {} into {} ranges",
+    cli.table,
+    index,
+    ranges.len()
+  );
+
+  let part_stems: Vec<String> = (0..ranges.len())
+    .map(|i| format!("{}_part{:03}", cli.table, i))
+    .collect();
+  let part_paths: Vec<String> = part_stems
+    .iter()
+    .map(|stem| format!("{}/{}.{}", folder_path, stem, sink::extension(cli)))
+    .collect();
+
+  let tasks =
+    ranges
+      .iter()
+      .zip(part_stems.iter().zip(&part_paths))
+      .map(|(&(lo, hi), (stem, path))| {
+        let sql = range_query(&cli.sql, index, lo, hi);
+        async move {
+          let sink = if cli.merge {
+            sink::create(export, path, None)?
+          } else {
+            let mut sink = sink::open(export, folder_path, stem)?;
+            sink.write_header()?;
+            sink
+          };
+          export.write_rows(&sql, Some(index), sink, None, None).await
+        }
+      });
+  let counts = try_join_all(tasks).await?;
+  info!(
+    "Exported {} rows in {} parts",
+    counts.iter().sum::<u64>(),
+    counts.len()
+  );
+
+  if cli.merge {
+    let output_path = format!("{}/{}.{}", folder_path, cli.table, sink::extension(cli));
+    let mut sink = sink::create(export, &output_path, None)?;
+    sink.write_header()?;
+    sink.finish()?;
+    let mut merged = OpenOptions::new().append(true).open(&output_path)?;
+    for path in &part_paths {
+      io::copy(&mut File::open(path)?, &mut merged)?;
+      std::fs::remove_file(path)?;
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn splits_evenly_and_covers_the_range() {
+    assert_eq!(split_ranges(1, 10, 3), vec![(1, 4), (5, 7), (8, 10)]);
+    assert_eq!(split_ranges(-5, 4, 2), vec![(-5, -1), (0, 4)]);
+    assert_eq!(split_ranges(7, 7, 4), vec![(7, 7)]);
+    assert_eq!(split_ranges(1, 3, 8).len(), 3);
+    assert!(split_ranges(5, 1, 2).is_empty());
+    assert!(split_ranges(1, 5, 0).is_empty());
+  }
+
+  #[test]
+  fn splits_full_bigint_ranges() {
+    let ranges = split_ranges(i128::from(i64::MIN), i128::from(i64::MAX), 4);
+    assert_eq!(ranges.first().unwrap().0, i128::from(i64::MIN));
+    assert_eq!(ranges.last().unwrap().1, i128::from(i64::MAX));
+    assert!(ranges.windows(2).all(|pair| pair[0].1 + 1 == pair[1].0));
+
+    let ranges = split_ranges(0, i128::from(u64::MAX), 2);
+    assert_eq!(
+      ranges,
+      vec![(0, i128::from(i64::MAX)), (1 << 63, i128::from(u64::MAX))]
+    );
+  }
+
+  #[test]
+  fn range_queries() {
+    assert_eq!(
+      range_query("SELECT * FROM t;", "id", 1, 10),
+      "SELECT * FROM (SELECT * FROM t) t WHERE t.`id` BETWEEN 1 AND 10 ORDER BY t.`id`"
+    );
+    assert_eq!(
+      range_query("SELECT * FROM t", "id", 9_223_372_036_854_775_808, u64::MAX.into()),
+      "SELECT * FROM (SELECT * FROM t) t WHERE t.`id` BETWEEN 9223372036854775808 AND 18446744073709551615 ORDER BY t.`id`"
+    );
+  }
+}
@@ -0,0 +1,394 @@
+use std::{fs::File, sync::Arc};
+
+use arrow_array::{
+  types::{
+    ArrowPrimitiveType, Date32Type, Decimal128Type, Decimal256Type, DecimalType, Float32Type,
+    Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, TimestampMicrosecondType, UInt16Type,
+    UInt32Type, UInt64Type, UInt8Type,
+  },
+  ArrayRef, BinaryArray, PrimitiveArray, RecordBatch, StringArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use parquet::{
+  arrow::ArrowWriter,
+  basic::{Compression, GzipLevel},
+  file::properties::WriterProperties,
+};
+
+use crate::{sink::Sink, value::Value};
+
+/// Rows collected before they are handed to the writer as one record batch
+const BATCH_ROWS: usize = 8192;
+
+/// Widest DECIMAL a 128 bit unscaled value holds
+const DECIMAL128_MAX_PRECISION: u8 = 38;
+
+/// Compression of Parquet data pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ParquetCodec {
+  Uncompressed,
+  Snappy,
+  Gzip,
+}
+
+impl From<ParquetCodec> for Compression {
+  fn from(codec: ParquetCodec) -> Self {
+    match codec {
+      ParquetCodec::Uncompressed => Compression::UNCOMPRESSED,
+      ParquetCodec::Snappy => Compression::SNAPPY,
+      ParquetCodec::Gzip => Compression::GZIP(GzipLevel::default()),
+    }
+  }
+}
+
+/// Arrow type of a column, derived from its MySQL type name. DECIMAL needs the `DECIMAL(p,s)`
+/// form with the source precision and scale, a bare DECIMAL is written as text.
+fn data_type(type_name: &str) -> DataType {
+  if let Some((precision, scale)) = decimal_digits(type_name) {
+    return if precision <= DECIMAL128_MAX_PRECISION {
+      DataType::Decimal128(precision, scale)
+    } else {
+      DataType::Decimal256(precision, scale)
+    };
+  }
+  match type_name {
+    "BOOLEAN" | "TINYINT" => DataType::Int8,
+    "SMALLINT" => DataType::Int16,
+    "MEDIUMINT" | "INT" => DataType::Int32,
+    "BIGINT" => DataType::Int64,
+    "TINYINT UNSIGNED" => DataType::UInt8,
+    "SMALLINT UNSIGNED" | "YEAR" => DataType::UInt16,
+    "MEDIUMINT UNSIGNED" | "INT UNSIGNED" => DataType::UInt32,
+    "BIGINT UNSIGNED" | "BIT" => DataType::UInt64,
+    "FLOAT" => DataType::Float32,
+    "DOUBLE" => DataType::Float64,
+    "DATE" => DataType::Date32,
+    // no time zone, the values are the server's wall clock
+    "DATETIME" | "TIMESTAMP" => DataType::Timestamp(TimeUnit::Microsecond, None),
+    "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB" | "GEOMETRY" => {
+      DataType::Binary
+    }
+    // CHAR, VARCHAR, TEXT variants, ENUM, SET, TIME, JSON and anything unknown
+    _ => DataType::Utf8,
+  }
+}
+
+/// Precision and scale of a `DECIMAL(p,s)` type name
+fn decimal_digits(type_name: &str) -> Option<(u8, i8)> {
+  let (precision, scale) = type_name
+    .strip_prefix("DECIMAL(")?
+    .strip_suffix(')')?
+    .split_once(',')?;
+  Some((precision.parse().ok()?, scale.parse().ok()?))
+}
+
+/// Apache Parquet file written by the arrow writer, one optional column per result column.
+/// Rows are collected into record batches, and the writer closes a row group once it holds
+/// `--row-group-rows` rows.
+pub struct ParquetSink {
+  writer: ArrowWriter<File>,
+  schema: SchemaRef,
+  rows: Vec<Vec<Value>>,
+}
+
+impl ParquetSink {
+  pub fn new(
+    names: &[&str],
+    type_names: &[String],
+    codec: ParquetCodec,
+    row_group_rows: usize,
+    file: File,
+  ) -> parquet::errors::Result<Self> {
+    let schema = Arc::new(Schema::new(
+      names
+        .iter()
+        .zip(type_names)
+        .map(|(name, type_name)| Field::new(*name, data_type(type_name), true))
+        .collect::<Vec<_>>(),
+    ));
+    let props = WriterProperties::builder()
+      .set_compression(codec.into())
+      .set_max_row_group_size(row_group_rows.max(1))
+      .build();
+    Ok(ParquetSink {
+      writer: ArrowWriter::try_new(file, schema.clone(), Some(props))?,
+      schema,
+      rows: Vec::with_capacity(BATCH_ROWS),
+    })
+  }
+
+  /// Hands the collected rows to the writer as one record batch
+  fn write_batch(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    if self.rows.is_empty() {
+      return Ok(());
+    }
+    let columns = self
+      .schema
+      .fields()
+      .iter()
+      .enumerate()
+      .map(|(num, field)| array(field, self.rows.iter().map(|row| &row[num])))
+      .collect::<Result<Vec<_>, _>>()?;
+    self.rows.clear();
+    let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+    self.writer.write(&batch)?;
+    Ok(())
+  }
+}
+
+impl Sink for ParquetSink {
+  fn write_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    // the names are part of the schema in the footer
+    Ok(())
+  }
+
+  fn write_row(&mut self, row: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+    self.rows.push(row.to_vec());
+    if self.rows.len() >= BATCH_ROWS {
+      self.write_batch()?;
+    }
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    self.write_batch()
+  }
+
+  fn offset(&self) -> std::io::Result<u64> {
+    // the open row group is counted so size limits see it before it is written
+    Ok((self.writer.bytes_written() + self.writer.in_progress_size()) as u64)
+  }
+
+  fn finish(mut self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+    self.write_batch()?;
+    self.writer.close()?;
+    Ok(())
+  }
+}
+
+/// Builds the array of one column from its values
+fn array<'a>(field: &Field, values: impl Iterator<Item = &'a Value>) -> Result<ArrayRef, String> {
+  let name = field.name();
+  let mismatch = |value: &Value| {
+    format!(
+      "column `{}` cannot hold {:?} as {}",
+      name,
+      value,
+      field.data_type()
+    )
+  };
+  let array: ArrayRef =
+    match field.data_type() {
+      DataType::Int8 => Arc::new(ints::<Int8Type>(name, values, mismatch)?),
+      DataType::Int16 => Arc::new(ints::<Int16Type>(name, values, mismatch)?),
+      DataType::Int32 => Arc::new(ints::<Int32Type>(name, values, mismatch)?),
+      DataType::Int64 => Arc::new(ints::<Int64Type>(name, values, mismatch)?),
+      DataType::UInt8 => Arc::new(ints::<UInt8Type>(name, values, mismatch)?),
+      DataType::UInt16 => Arc::new(ints::<UInt16Type>(name, values, mismatch)?),
+      DataType::UInt32 => Arc::new(ints::<UInt32Type>(name, values, mismatch)?),
+      DataType::UInt64 => Arc::new(ints::<UInt64Type>(name, values, mismatch)?),
+      DataType::Float32 => Arc::new(primitive::<Float32Type>(values, |value| match value {
+        Value::Float(v) => Ok(Some(*v)),
+        value => Err(mismatch(value)),
+      })?),
+      DataType::Float64 => Arc::new(primitive::<Float64Type>(values, |value| match value {
+        Value::Double(v) => Ok(Some(*v)),
+        Value::Float(v) => Ok(Some(f64::from(*v))),
+        value => Err(mismatch(value)),
+      })?),
+      DataType::Decimal128(precision, scale) => Arc::new(
+        decimals::<Decimal128Type>(name, values, *precision, *scale, mismatch)?
+          .with_precision_and_scale(*precision, *scale)
+          .map_err(|err| err.to_string())?,
+      ),
+      DataType::Decimal256(precision, scale) => Arc::new(
+        decimals::<Decimal256Type>(name, values, *precision, *scale, mismatch)?
+          .with_precision_and_scale(*precision, *scale)
+          .map_err(|err| err.to_string())?,
+      ),
+      // zero dates are decoded as text and have no Parquet representation
+      DataType::Date32 => Arc::new(primitive::<Date32Type>(values, |value| match value {
+        Value::Date(date) => Ok(Some(date.signed_duration_since(epoch()).num_days() as i32)),
+        Value::Text(_) => Ok(None),
+        value => Err(mismatch(value)),
+      })?),
+      DataType::Timestamp(..) => Arc::new(primitive::<TimestampMicrosecondType>(
+        values,
+        |value| match value {
+          Value::DateTime(datetime) => Ok(Some(datetime.and_utc().timestamp_micros())),
+          Value::Text(_) => Ok(None),
+          value => Err(mismatch(value)),
+        },
+      )?),
+      DataType::Binary => {
+        let values: Vec<Option<Vec<u8>>> = values
+          .map(|value| match value {
+            Value::Null => None,
+            Value::Binary(bytes) | Value::Geometry(bytes) => Some(bytes.clone()),
+            value => Some(value.render().unwrap_or_default().into_bytes()),
+          })
+          .collect();
+        Arc::new(BinaryArray::from_iter(values.iter().map(Option::as_deref)))
+      }
+      _ => Arc::new(StringArray::from_iter(values.map(Value::render))),
+    };
+  Ok(array)
+}
+
+/// Values of one column, NULL kept as NULL and everything else passed through `convert`
+fn primitive<'a, T: ArrowPrimitiveType>(
+  values: impl Iterator<Item = &'a Value>,
+  convert: impl Fn(&Value) -> Result<Option<T::Native>, String>,
+) -> Result<PrimitiveArray<T>, String> {
+  values
+    .map(|value| match value {
+      Value::Null => Ok(None),
+      value => convert(value),
+    })
+    .collect()
+}
+
+/// Integers checked against the range of the column type
+fn ints<'a, T: ArrowPrimitiveType>(
+  name: &str,
+  values: impl Iterator<Item = &'a Value>,
+  mismatch: impl Fn(&Value) -> String,
+) -> Result<PrimitiveArray<T>, String>
+where
+  T::Native: TryFrom<i128>,
+{
+  primitive::<T>(values, |value| {
+    let v = match value {
+      Value::Int(v) => i128::from(*v),
+      Value::UInt(v) => i128::from(*v),
+      value => return Err(mismatch(value)),
+    };
+    match T::Native::try_from(v) {
+      Ok(v) => Ok(Some(v)),
+      Err(_) => Err(format!("column `{}` cannot hold {}", name, v)),
+    }
+  })
+}
+
+/// Unscaled DECIMAL values parsed from the server's text at the column's scale
+fn decimals<'a, T: DecimalType>(
+  name: &str,
+  values: impl Iterator<Item = &'a Value>,
+  precision: u8,
+  scale: i8,
+  mismatch: impl Fn(&Value) -> String,
+) -> Result<PrimitiveArray<T>, String> {
+  primitive::<T>(values, |value| {
+    let text = match value {
+      Value::Decimal(_) | Value::Int(_) | Value::UInt(_) => value.render().unwrap_or_default(),
+      value => return Err(mismatch(value)),
+    };
+    match arrow_cast::parse::parse_decimal::<T>(&text, precision, scale) {
+      Ok(v) => Ok(Some(v)),
+      Err(_) => Err(format!(
+        "column `{}` cannot hold {} as DECIMAL({},{})",
+        name, text, precision, scale
+      )),
+    }
+  })
+}
+
+fn epoch() -> NaiveDate {
+  NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use arrow_array::{cast::AsArray, Array};
+  use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+  use super::*;
+
+  #[test]
+  fn maps_mysql_types() {
+    assert_eq!(data_type("TINYINT UNSIGNED"), DataType::UInt8);
+    assert_eq!(data_type("BIGINT"), DataType::Int64);
+    assert_eq!(data_type("DECIMAL(10,2)"), DataType::Decimal128(10, 2));
+    assert_eq!(data_type("DECIMAL(65,30)"), DataType::Decimal256(65, 30));
+    assert_eq!(data_type("DECIMAL"), DataType::Utf8);
+    assert_eq!(
+      data_type("DATETIME"),
+      DataType::Timestamp(TimeUnit::Microsecond, None)
+    );
+    assert_eq!(data_type("GEOMETRY"), DataType::Binary);
+  }
+
+  #[test]
+  fn checks_values_against_the_column() {
+    let field = Field::new("n", DataType::Int8, true);
+    assert!(array(&field, [Value::Int(127), Value::Null].iter()).is_ok());
+    assert!(array(&field, [Value::Int(128)].iter()).is_err());
+    assert!(array(&field, [Value::Text("1".into())].iter()).is_err());
+
+    let field = Field::new("d", DataType::Decimal128(5, 2), true);
+    assert!(array(&field, [Value::Decimal("999.99".into())].iter()).is_ok());
+    assert!(array(&field, [Value::Decimal("1000.00".into())].iter()).is_err());
+  }
+
+  #[test]
+  fn writes_a_readable_file() {
+    let path = std::env::temp_dir().join(format!("mysql2csv-{}.parquet", std::process::id()));
+    let types = [
+      "INT".to_string(),
+      "DECIMAL(12,2)".to_string(),
+      "VARCHAR".to_string(),
+    ];
+    let mut sink: Box<dyn Sink> = Box::new(
+      ParquetSink::new(
+        &["id", "price", "name"],
+        &types,
+        ParquetCodec::Snappy,
+        2,
+        File::create(&path).unwrap(),
+      )
+      .unwrap(),
+    );
+    sink.write_header().unwrap();
+    for n in 0..5 {
+      let name = match n {
+        3 => Value::Null,
+        n => Value::Text(format!("row {}", n)),
+      };
+      sink
+        .write_row(&[Value::Int(n), Value::Decimal(format!("-{}.05", n)), name])
+        .unwrap();
+    }
+    sink.finish().unwrap();
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+    assert_eq!(builder.metadata().num_row_groups(), 3);
+    let batches: Vec<RecordBatch> = builder.build().unwrap().collect::<Result<_, _>>().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+      batches[0].schema().field(1).data_type(),
+      &DataType::Decimal128(12, 2)
+    );
+    let mut prices = Vec::new();
+    let mut names = Vec::new();
+    for batch in &batches {
+      let price = batch.column(1).as_primitive::<Decimal128Type>();
+      prices.extend((0..price.len()).map(|row| price.value_as_string(row)));
+      let name = batch.column(2).as_string::<i32>();
+      names.extend((0..name.len()).map(|row| name.is_valid(row).then(|| name.value(row))));
+    }
+    assert_eq!(prices, ["-0.05", "-1.05", "-2.05", "-3.05", "-4.05"]);
+    assert_eq!(
+      names,
+      [
+        Some("row 0"),
+        Some("row 1"),
+        Some("row 2"),
+        None,
+        Some("row 4")
+      ]
+    );
+  }
+}
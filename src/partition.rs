@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use log::info;
+
+use crate::{
+  export::Export,
+  sink::{self, Sink},
+  value::Value,
+};
+
+/// Directory value Hive uses for NULL partition keys
+const DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// Routes each row into `{folder}/col1=value/col2=value/part-{stem}-NNNNN.{ext}`.
+/// Partition columns live in the directory names only, as Spark and Trino expect.
+/// At most `--max-open-partitions` writers are open; the least recently used one
+/// is finished to make room, and its partition continues in the next part file.
+pub struct PartitionSink<'a> {
+  export: &'a Export<'a>,
+  folder_path: String,
+  stem: String,
+  partition_pos: Vec<usize>,
+  data_pos: Vec<usize>,
+  data_type_names: Vec<String>,
+  writers: HashMap<String, (Box<dyn Sink>, u64)>,
+  parts: HashMap<String, usize>,
+  tick: u64,
+  header: bool,
+  data: Vec<Value>,
+}
+
+impl<'a> PartitionSink<'a> {
+  pub fn new(
+    export: &'a Export<'a>,
+    folder_path: &str,
+    stem: &str,
+  ) -> Result<Self, Box<dyn std::error::Error>> {
+    let cli = export.cli;
+    let partition_pos = cli
+      .partition_by
+      .iter()
+      .map(|column| {
+        export
+          .vec_col_name
+          .iter()
+          .position(|name| name == column)
+          .ok_or_else(|| format!("partition column `{}` is not in the query result", column))
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    let data_pos: Vec<usize> = (0..export.vec_col_name.len())
+      .filter(|pos| !partition_pos.contains(pos))
+      .collect();
+
+    Ok(PartitionSink {
+      export,
+      folder_path: folder_path.to_string(),
+      stem: stem.to_string(),
+      data_type_names: data_pos
+        .iter()
+        .map(|pos| export.vec_col_type_name[*pos].clone())
+        .collect(),
+      partition_pos,
+      data_pos,
+      writers: HashMap::new(),
+      parts: HashMap::new(),
+      tick: 0,
+      header: false,
+      data: Vec::new(),
+    })
+  }
+
+  fn partition_dir(&self, row: &[Value]) -> String {
+    self
+      .partition_pos
+      .iter()
+      .map(|pos| {
+        let value = row[*pos]
+          .render()
+          .map(|text| escape_path(&text))
+          .unwrap_or_else(|| DEFAULT_PARTITION.to_string());
+        format!("{}={}", escape_path(self.export.vec_col_name[*pos]), value)
+      })
+      .collect::<Vec<_>>()
+      .join("/")
+  }
+
+  fn open_writer(&mut self, dir: &str) -> Result<Box<dyn Sink>, Box<dyn std::error::Error>> {
+    if self.writers.len() >= self.export.cli.max_open_partitions.max(1) {
+      let oldest = self
+        .writers
+        .iter()
+        .min_by_key(|(_, (_, used))| *used)
+        .map(|(dir, _)| dir.clone());
+      if let Some((sink, _)) = oldest.and_then(|dir| self.writers.remove(&dir)) {
+        sink.finish()?;
+      }
+    }
+
+    let part = self.parts.entry(dir.to_string()).or_insert(0);
+    *part += 1;
+    let dir_path = format!("{}/{}", self.folder_path, dir);
+    std::fs::create_dir_all(&dir_path)?;
+    let path = format!(
+      "{}/part-{}-{:05}.{}",
+      dir_path,
+      self.stem,
+      part,
+      sink::extension(self.export.cli)
+    );
+
+    let names: Vec<&str> = self
+      .data_pos
+      .iter()
+      .map(|pos| self.export.vec_col_name[*pos])
+      .collect();
+    let mut sink =
+      sink::create_for_columns(self.export.cli, &names, &self.data_type_names, &path, None)?;
+    if self.header {
+      sink.write_header()?;
+    }
+    Ok(sink)
+  }
+}
+
+impl Sink for PartitionSink<'_> {
+  fn write_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    // every partition file gets the header when it is opened
+    self.header = true;
+    Ok(())
+  }
+
+  fn write_row(&mut self, row: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = self.partition_dir(row);
+    self.tick += 1;
+    if !self.writers.contains_key(&dir) {
+      let sink = self.open_writer(&dir)?;
+      self.writers.insert(dir.clone(), (sink, 0));
+    }
+
+    self.data.clear();
+    self
+      .data
+      .extend(self.data_pos.iter().map(|pos| row[*pos].clone()));
+    if let Some((sink, used)) = self.writers.get_mut(&dir) {
+      *used = self.tick;
+      sink.write_row(&self.data)?;
+    }
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    for (sink, _) in self.writers.values_mut() {
+      sink.flush()?;
+    }
+    Ok(())
+  }
+
+  fn offset(&self) -> std::io::Result<u64> {
+    Ok(0)
+  }
+
+  fn finish(self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+    for (sink, _) in self.writers.into_values() {
+      sink.finish()?;
+    }
+    info!("Wrote {} partitions", self.parts.len());
+    Ok(())
+  }
+}
+
+/// Escapes a partition directory component the way Hive does
+fn escape_path(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for ch in text.chars() {
+    match ch {
+      '\u{01}'..='\u{1f}'
+      | '"'
+      | '#'
+      | '%'
+      | '\''
+      | '*'
+      | '/'
+      | ':'
+      | '='
+      | '?'
+      | '\\'
+      | '\u{7f}'
+      | '{'
+      | '['
+      | ']'
+      | '^' => escaped.push_str(&format!("%{:02X}", ch as u32)),
+      ch => escaped.push(ch),
+    }
+  }
+
+  escaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn escapes_like_hive() {
+    assert_eq!(escape_path("2024-03-01"), "2024-03-01");
+    assert_eq!(escape_path("a/b=c"), "a%2Fb%3Dc");
+    assert_eq!(escape_path("10:30 50%"), "10%3A30 50%25");
+    assert_eq!(escape_path("x\ny\u{7f}"), "x%0Ay%7F");
+    assert_eq!(
+      escape_path(r#"{[^*?"'#\]}"#),
+      "%7B%5B%5E%2A%3F%22%27%23%5C%5D}"
+    );
+    assert_eq!(escape_path("München"), "München");
+  }
+}
@@ -0,0 +1,171 @@
+use clap::ValueEnum;
+use indicatif::ProgressStyle;
+use log::warn;
+use sqlx::{Executor, MySqlPool, Row};
+
+use crate::{export::Export, Cli};
+
+/// How the progress bar total is obtained; without one the bar is a throughput spinner
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+  /// run `SELECT COUNT(*)` over the query before exporting
+  Exact,
+  /// use the optimizer row estimate of `EXPLAIN`, or the table statistics
+  Estimate,
+  /// skip counting and show a throughput spinner
+  #[value(name = "none")]
+  NoCount,
+}
+
+/// Turns the spinner of `export` into a bar once the rows `sql` will return are known;
+/// counted rows are added to the position so a resumed export shows its overall progress
+pub async fn set_total(export: &Export<'_>, sql: &str) -> Result<(), Box<dyn std::error::Error>> {
+  if let Some(rows) = total_rows(export.pool, export.cli, sql).await {
+    export.pb.set_length(export.pb.position() + rows);
+    export.pb.set_style(
+      ProgressStyle::default_bar()
+        .template(
+          "{spinner:.green} {prefix}[{elapsed_precise}] [{bar:40.cyan/orange}] {pos}/{len} ({eta})",
+        )?
+        .progress_chars("=>-"),
+    );
+  }
+
+  Ok(())
+}
+
+/// Rows `sql` will return, `None` when `--progress none` is set or no count is available;
+/// a failed count only costs the bar its total
+async fn total_rows(pool: &MySqlPool, cli: &Cli, sql: &str) -> Option<u64> {
+  let sql = sql.trim().trim_end_matches(';');
+  match cli.progress {
+    ProgressMode::Exact => {
+      let count_query = format!("SELECT COUNT(*) FROM ({}) t", sql);
+      match sqlx::query_scalar::<_, i64>(&count_query)
+        .fetch_one(pool)
+        .await
+      {
+        Ok(count) => Some(count as u64),
+        Err(err) => {
+          warn!(
+            "Cannot count the rows of {}, showing a spinner: {}",
+            cli.table, err
+          );
+          None
+        }
+      }
+    }
+    ProgressMode::Estimate => match explain_rows(pool, sql).await {
+      Ok(Some(rows)) => Some(rows),
+      // table statistics only describe the unfiltered user query
+      Ok(None) | Err(_) if sql == cli.sql.trim().trim_end_matches(';') => {
+        table_rows(pool, cli).await
+      }
+      Ok(None) | Err(_) => None,
+    },
+    ProgressMode::NoCount => None,
+  }
+}
+
+/// Rows the optimizer expects the query to return: the product of `rows * filtered`
+/// over the tables joined in the outermost select
+async fn explain_rows(pool: &MySqlPool, sql: &str) -> Result<Option<u64>, sqlx::Error> {
+  let plan = pool.fetch_all(format!("EXPLAIN {}", sql).as_str()).await?;
+  let Some(first_id) = plan
+    .first()
+    .and_then(|row| row.try_get_unchecked::<Option<i64>, _>("id").ok().flatten())
+  else {
+    return Ok(None);
+  };
+
+  let mut estimate = 1.0;
+  for row in &plan {
+    if row.try_get_unchecked::<Option<i64>, _>("id")?.unwrap_or(0) != first_id {
+      continue;
+    }
+    let rows = row
+      .try_get_unchecked::<Option<f64>, _>("rows")
+      .ok()
+      .flatten()
+      .unwrap_or(1.0);
+    let filtered = row
+      .try_get_unchecked::<Option<f64>, _>("filtered")
+      .ok()
+      .flatten()
+      .unwrap_or(100.0);
+    estimate *= rows * filtered / 100.0;
+  }
+
+  Ok(Some(estimate.round() as u64))
+}
+
+/// `TABLE_ROWS` statistics of `--table`, approximate for InnoDB
+async fn table_rows(pool: &MySqlPool, cli: &Cli) -> Option<u64> {
+  let rows: Result<Option<Option<u64>>, _> = sqlx::query_scalar(
+    "SELECT CAST(TABLE_ROWS AS UNSIGNED) FROM information_schema.TABLES \
+     WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ?",
+  )
+  .bind(&cli.db)
+  .bind(&cli.table)
+  .fetch_optional(pool)
+  .await;
+  let rows = rows.ok().flatten().flatten();
+  if rows.is_none() {
+    warn!("No row estimate for {}, showing a spinner", cli.table);
+  }
+
+  rows
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use clap::Parser;
+  use indicatif::ProgressBar;
+  use sqlx::mysql::MySqlPoolOptions;
+
+  use super::*;
+  use crate::transform::Pipeline;
+
+  /// Counts `sql` against a server that refuses connections
+  async fn length_after_count(args: &[&str]) -> Option<u64> {
+    let cli = Cli::parse_from(
+      ["mysql2csv", "-D", "shop", "-t", "orders"]
+        .iter()
+        .chain(args),
+    );
+    let pool = MySqlPoolOptions::new()
+      .acquire_timeout(Duration::from_millis(500))
+      .connect_lazy("mysql://root@127.0.0.1:1/shop")
+      .unwrap();
+    let pipeline = Pipeline::new(&[], "", &[], None, &[]).unwrap();
+    let pb = ProgressBar::hidden();
+    let export = Export {
+      pool: &pool,
+      cli: &cli,
+      query_col_name: &[],
+      query_col_type: &[],
+      projection: &[],
+      vec_col_name: &[],
+      vec_col_type_name: &[],
+      pipeline: &pipeline,
+      pb: &pb,
+    };
+    set_total(&export, "SELECT * FROM orders").await.unwrap();
+    pb.length()
+  }
+
+  #[test]
+  fn estimates_by_default() {
+    let cli = Cli::parse_from(["mysql2csv", "-D", "shop"]);
+    assert_eq!(cli.progress, ProgressMode::Estimate);
+  }
+
+  #[tokio::test]
+  async fn failed_counts_leave_the_total_unknown() {
+    assert_eq!(length_after_count(&["--progress", "exact"]).await, None);
+    assert_eq!(length_after_count(&["--progress", "estimate"]).await, None);
+    assert_eq!(length_after_count(&["--progress", "none"]).await, None);
+  }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use sqlx::mysql::MySqlDatabaseError;
+
+use crate::Cli;
+
+/// When and how often a failed query is re-issued
+pub struct RetryPolicy {
+  attempts: u32,
+  backoff: Duration,
+  codes: Vec<u16>,
+}
+
+impl RetryPolicy {
+  pub fn new(cli: &Cli) -> Self {
+    RetryPolicy {
+      attempts: cli.retries,
+      backoff: Duration::from_millis(cli.retry_backoff_ms),
+      codes: cli.retry_codes.clone(),
+    }
+  }
+
+  pub fn attempts(&self) -> u32 {
+    self.attempts
+  }
+
+  /// Lost connections and pool timeouts are always transient, server errors only when their
+  /// MySQL error number is in `--retry-codes`
+  pub fn is_transient(&self, err: &sqlx::Error) -> bool {
+    match err {
+      sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+      sqlx::Error::Database(db) => db
+        .try_downcast_ref::<MySqlDatabaseError>()
+        .is_some_and(|db| self.codes.contains(&db.number())),
+      _ => false,
+    }
+  }
+
+  /// Exponential backoff before retry number `attempt`, counting from 1, capped at a minute
+  pub fn delay(&self, attempt: u32) -> Duration {
+    self
+      .backoff
+      .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+      .min(Duration::from_secs(60))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+
+  use super::*;
+
+  fn policy(args: &[&str]) -> RetryPolicy {
+    RetryPolicy::new(&Cli::parse_from(
+      ["mysql2csv", "-D", "shop"].iter().chain(args),
+    ))
+  }
+
+  #[test]
+  fn backs_off_exponentially_up_to_a_minute() {
+    let policy = policy(&["--retries", "5", "--retry-backoff-ms", "500"]);
+    assert_eq!(policy.attempts(), 5);
+    let delays: Vec<u128> = (1..=4).map(|n| policy.delay(n).as_millis()).collect();
+    assert_eq!(delays, [500, 1000, 2000, 4000]);
+    assert_eq!(policy.delay(8), Duration::from_secs(60));
+    assert_eq!(policy.delay(u32::MAX), Duration::from_secs(60));
+  }
+
+  #[test]
+  fn retries_lost_connections_only() {
+    let policy = policy(&[]);
+    let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+    assert!(policy.is_transient(&sqlx::Error::Io(reset)));
+    assert!(policy.is_transient(&sqlx::Error::PoolTimedOut));
+    assert!(!policy.is_transient(&sqlx::Error::RowNotFound));
+    assert!(!policy.is_transient(&sqlx::Error::ColumnNotFound("id".to_string())));
+  }
+}
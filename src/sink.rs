@@ -0,0 +1,490 @@
+use std::{
+  borrow::Cow,
+  fs::{File, OpenOptions},
+  io::{BufWriter, Seek, SeekFrom, Write},
+};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::ValueEnum;
+
+use crate::{
+  compress::Output,
+  export::Export,
+  parquet::ParquetSink,
+  partition::PartitionSink,
+  split::SplitSink,
+  value::{quote_literal, Value},
+  xlsx::XlsxSink,
+  Cli,
+};
+
+/// Output file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+  Csv,
+  /// one JSON object per line, binary columns as standard base64
+  Jsonl,
+  Sql,
+  Xlsx,
+  Parquet,
+}
+
+impl Format {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      Format::Csv => "csv",
+      Format::Jsonl => "jsonl",
+      Format::Sql => "sql",
+      Format::Xlsx => "xlsx",
+      Format::Parquet => "parquet",
+    }
+  }
+
+  /// Whether a file can be truncated at a row boundary and appended to, or concatenated
+  pub fn appendable(&self) -> bool {
+    !matches!(self, Format::Xlsx | Format::Parquet)
+  }
+
+  /// Whether column names are keys (JSON objects, Parquet fields, INSERT column lists)
+  /// and so must be unique
+  pub fn unique_names(&self) -> bool {
+    matches!(self, Format::Jsonl | Format::Parquet | Format::Sql)
+  }
+}
+
+/// Destination for exported rows
+pub trait Sink {
+  /// Writes the column names, skipped when appending to a resumed file
+  fn write_header(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+
+  fn write_row(&mut self, row: &[Value]) -> Result<(), Box<dyn std::error::Error>>;
+
+  fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+
+  /// Bytes persisted so far, valid right after `flush`
+  fn offset(&self) -> std::io::Result<u64>;
+
+  /// Completes the file once all rows are written
+  fn finish(self: Box<Self>) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// File extension for the chosen format and compression, e.g. `csv.gz`
+pub fn extension(cli: &Cli) -> String {
+  match cli.compress {
+    Some(compression) => format!("{}.{}", cli.format.extension(), compression.extension()),
+    None => cli.format.extension().to_string(),
+  }
+}
+
+/// Whether an interrupted file can be truncated to a checkpoint and appended to
+pub fn resumable(cli: &Cli) -> bool {
+  cli.format.appendable() && cli.compress.is_none()
+}
+
+/// Opens the output for `{folder_path}/{stem}`, routed into partition directories with
+/// `--partition-by`, or split into numbered parts with `--max-rows-per-file`/`--max-bytes-per-file`
+pub fn open<'a>(
+  export: &'a Export<'a>,
+  folder_path: &str,
+  stem: &str,
+) -> Result<Box<dyn Sink + 'a>, Box<dyn std::error::Error>> {
+  let cli = export.cli;
+  if !cli.partition_by.is_empty() {
+    return Ok(Box::new(PartitionSink::new(export, folder_path, stem)?));
+  }
+  if cli.max_rows_per_file.is_some() || cli.max_bytes_per_file.is_some() {
+    return Ok(Box::new(SplitSink::new(export, folder_path, stem)));
+  }
+  let path = format!("{}/{}.{}", folder_path, stem, extension(cli));
+  create(export, &path, None).map(|sink| sink as Box<dyn Sink + 'a>)
+}
+
+/// Opens the sink for `path` in the format chosen by `--format`.
+/// With `resume_at`, the existing file is truncated to that byte offset and appended to.
+pub fn create(
+  export: &Export<'_>,
+  path: &str,
+  resume_at: Option<u64>,
+) -> Result<Box<dyn Sink>, Box<dyn std::error::Error>> {
+  create_for_columns(
+    export.cli,
+    export.vec_col_name,
+    export.vec_col_type_name,
+    path,
+    resume_at,
+  )
+}
+
+/// Like `create`, for a subset of the result columns
+pub fn create_for_columns(
+  cli: &Cli,
+  vec_col_name: &[&str],
+  vec_col_type_name: &[String],
+  path: &str,
+  resume_at: Option<u64>,
+) -> Result<Box<dyn Sink>, Box<dyn std::error::Error>> {
+  let file = match resume_at {
+    Some(offset) => {
+      let mut file = OpenOptions::new().write(true).open(path)?;
+      file.set_len(offset)?;
+      file.seek(SeekFrom::End(0))?;
+      file
+    }
+    None => File::create(path)?,
+  };
+
+  let output = |file| Output::new(file, cli.compress, cli.compress_level);
+  let sink: Box<dyn Sink> = match cli.format {
+    Format::Csv => Box::new(CsvSink::new(cli, vec_col_name, output(file)?)),
+    Format::Jsonl => Box::new(JsonlSink::new(vec_col_name, output(file)?)?),
+    Format::Sql => Box::new(SqlSink::new(
+      cli,
+      vec_col_name,
+      vec_col_type_name,
+      output(file)?,
+    )),
+    // both compress internally and are written straight to the file
+    Format::Xlsx => Box::new(XlsxSink::new(vec_col_name, file)),
+    Format::Parquet => Box::new(ParquetSink::new(
+      vec_col_name,
+      vec_col_type_name,
+      cli.parquet_codec,
+      cli.row_group_rows,
+      file,
+    )?),
+  };
+
+  Ok(sink)
+}
+
+/// Delimited text, NULL written as `--null`
+pub struct CsvSink {
+  wtr: csv::Writer<Output>,
+  names: Vec<String>,
+  null: String,
+}
+
+impl CsvSink {
+  pub fn new(cli: &Cli, names: &[&str], file: Output) -> Self {
+    let delim = cli.delim.as_bytes().first().cloned().unwrap_or(b'|');
+    CsvSink {
+      wtr: csv::WriterBuilder::new().delimiter(delim).from_writer(file),
+      names: names.iter().map(|name| name.to_string()).collect(),
+      null: cli.null.clone(),
+    }
+  }
+}
+
+impl Sink for CsvSink {
+  fn write_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    self.wtr.write_record(&self.names)?;
+    Ok(())
+  }
+
+  fn write_row(&mut self, row: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+    for value in row {
+      match value.render() {
+        Some(text) => self.wtr.write_field(text)?,
+        None => self.wtr.write_field(&self.null)?,
+      }
+    }
+    self.wtr.write_record(None::<&[u8]>)?;
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    self.wtr.flush()?;
+    Ok(())
+  }
+
+  fn offset(&self) -> std::io::Result<u64> {
+    self.wtr.get_ref().offset()
+  }
+
+  fn finish(self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut output = self.wtr.into_inner().map_err(|err| err.into_error())?;
+    output.finish()?;
+    Ok(())
+  }
+}
+
+/// One JSON object per line keyed by column name, in column order.
+/// DECIMAL is written as a string to keep its precision, JSON columns are embedded as-is and
+/// BINARY/BLOB values as standard base64 strings with padding.
+pub struct JsonlSink {
+  wtr: BufWriter<Output>,
+  keys: Vec<String>,
+}
+
+impl JsonlSink {
+  pub fn new(names: &[&str], file: Output) -> serde_json::Result<Self> {
+    Ok(JsonlSink {
+      wtr: BufWriter::new(file),
+      keys: names
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<_, _>>()?,
+    })
+  }
+}
+
+impl Sink for JsonlSink {
+  fn write_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    // no header line, the names are the object keys
+    Ok(())
+  }
+
+  fn write_row(&mut self, row: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+    self.wtr.write_all(b"{")?;
+    for (num, value) in row.iter().enumerate() {
+      if num > 0 {
+        self.wtr.write_all(b",")?;
+      }
+      self.wtr.write_all(self.keys[num].as_bytes())?;
+      self.wtr.write_all(b":")?;
+      serde_json::to_writer(&mut self.wtr, &json_value(value))?;
+    }
+    self.wtr.write_all(b"}\n")?;
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    self.wtr.flush()?;
+    Ok(())
+  }
+
+  fn offset(&self) -> std::io::Result<u64> {
+    self.wtr.get_ref().offset()
+  }
+
+  fn finish(mut self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+    self.flush()?;
+    self.wtr.get_mut().finish()?;
+    Ok(())
+  }
+}
+
+fn json_value(value: &Value) -> serde_json::Value {
+  match value {
+    Value::Null => serde_json::Value::Null,
+    Value::Int(v) => (*v).into(),
+    Value::UInt(v) => (*v).into(),
+    // widening to f64 would print 1.1 as 1.100000023841858, go through the shortest f32 text
+    Value::Float(v) => v
+      .to_string()
+      .parse()
+      .ok()
+      .and_then(serde_json::Number::from_f64)
+      .map_or(serde_json::Value::Null, serde_json::Value::Number),
+    Value::Double(v) => {
+      serde_json::Number::from_f64(*v).map_or(serde_json::Value::Null, serde_json::Value::Number)
+    }
+    Value::Json(text) => {
+      serde_json::from_str(text).unwrap_or_else(|_| serde_json::Value::String(text.clone()))
+    }
+    Value::Binary(bytes) => BASE64_STANDARD.encode(bytes).into(),
+    other => other.render().into(),
+  }
+}
+
+/// `CREATE TABLE` DDL followed by multi-row `INSERT INTO` statements of `--insert-rows` rows.
+/// Every flush closes the open statement, so the file is valid SQL at each checkpoint.
+pub struct SqlSink {
+  wtr: BufWriter<Output>,
+  table: String,
+  columns: Vec<(String, Cow<'static, str>)>,
+  insert_rows: usize,
+  pending: usize,
+}
+
+impl SqlSink {
+  pub fn new(cli: &Cli, vec_col_name: &[&str], vec_col_type_name: &[String], file: Output) -> Self {
+    let columns = vec_col_name
+      .iter()
+      .zip(vec_col_type_name)
+      .map(|(name, type_name)| (quote_ident(name), ddl_type(type_name)))
+      .collect();
+    SqlSink {
+      wtr: BufWriter::new(file),
+      table: quote_ident(&cli.table),
+      columns,
+      insert_rows: cli.insert_rows.max(1),
+      pending: 0,
+    }
+  }
+
+  fn end_statement(&mut self) -> std::io::Result<()> {
+    if self.pending > 0 {
+      self.wtr.write_all(b";\n")?;
+      self.pending = 0;
+    }
+    Ok(())
+  }
+}
+
+impl Sink for SqlSink {
+  fn write_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    writeln!(self.wtr, "CREATE TABLE IF NOT EXISTS {} (", self.table)?;
+    for (num, (name, ddl)) in self.columns.iter().enumerate() {
+      let sep = if num + 1 < self.columns.len() {
+        ","
+      } else {
+        ""
+      };
+      writeln!(self.wtr, "  {} {} NULL{}", name, ddl, sep)?;
+    }
+    writeln!(self.wtr, ");")?;
+    Ok(())
+  }
+
+  fn write_row(&mut self, row: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+    if self.pending == 0 {
+      let names: Vec<&str> = self.columns.iter().map(|(name, _)| name.as_str()).collect();
+      write!(
+        self.wtr,
+        "INSERT INTO {} ({}) VALUES\n(",
+        self.table,
+        names.join(",")
+      )?;
+    } else {
+      self.wtr.write_all(b",\n(")?;
+    }
+    for (num, value) in row.iter().enumerate() {
+      if num > 0 {
+        self.wtr.write_all(b",")?;
+      }
+      self.wtr.write_all(sql_literal(value).as_bytes())?;
+    }
+    self.wtr.write_all(b")")?;
+    self.pending += 1;
+    if self.pending >= self.insert_rows {
+      self.end_statement()?;
+    }
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    self.end_statement()?;
+    self.wtr.flush()?;
+    Ok(())
+  }
+
+  fn offset(&self) -> std::io::Result<u64> {
+    self.wtr.get_ref().offset()
+  }
+
+  fn finish(mut self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+    self.flush()?;
+    self.wtr.get_mut().finish()?;
+    Ok(())
+  }
+}
+
+fn quote_ident(name: &str) -> String {
+  format!("`{}`", name.replace('`', "``"))
+}
+
+/// Column type for the generated DDL. Lengths are not part of the result metadata, so types
+/// are widened to ones that hold any value of the source type; DECIMAL keeps the precision and
+/// scale looked up for `--table` and is widened to DECIMAL(65,30) without them.
+fn ddl_type(type_name: &str) -> Cow<'static, str> {
+  if type_name.starts_with("DECIMAL(") {
+    return type_name.to_string().into();
+  }
+  let ddl = match type_name {
+    "BOOLEAN" => "TINYINT(1)",
+    "TINYINT" => "TINYINT",
+    "SMALLINT" => "SMALLINT",
+    "MEDIUMINT" => "MEDIUMINT",
+    "INT" => "INT",
+    "BIGINT" => "BIGINT",
+    "TINYINT UNSIGNED" => "TINYINT UNSIGNED",
+    "SMALLINT UNSIGNED" => "SMALLINT UNSIGNED",
+    "MEDIUMINT UNSIGNED" => "MEDIUMINT UNSIGNED",
+    "INT UNSIGNED" => "INT UNSIGNED",
+    "BIGINT UNSIGNED" => "BIGINT UNSIGNED",
+    "YEAR" => "YEAR",
+    "FLOAT" => "FLOAT",
+    "DOUBLE" => "DOUBLE",
+    "DECIMAL" => "DECIMAL(65,30)",
+    "BIT" => "BIT(64)",
+    "DATE" => "DATE",
+    "TIME" => "TIME(6)",
+    "DATETIME" => "DATETIME(6)",
+    "TIMESTAMP" => "TIMESTAMP(6)",
+    "TINYTEXT" => "TINYTEXT",
+    "TEXT" => "TEXT",
+    "MEDIUMTEXT" => "MEDIUMTEXT",
+    "TINYBLOB" => "TINYBLOB",
+    "BLOB" => "BLOB",
+    "MEDIUMBLOB" => "MEDIUMBLOB",
+    "BINARY" | "VARBINARY" | "LONGBLOB" => "LONGBLOB",
+    "JSON" => "JSON",
+    "GEOMETRY" => "GEOMETRY",
+    // CHAR, VARCHAR, ENUM, SET and anything unknown
+    _ => "LONGTEXT",
+  };
+  ddl.into()
+}
+
+/// Renders a value as a MySQL literal: numbers bare, binaries as hex, everything else quoted
+fn sql_literal(value: &Value) -> String {
+  match value {
+    Value::Null => "NULL".to_string(),
+    Value::Int(_) | Value::UInt(_) | Value::Float(_) | Value::Double(_) | Value::Decimal(_) => {
+      value.render().unwrap_or_default()
+    }
+    Value::Binary(bytes) | Value::Geometry(bytes) if bytes.is_empty() => "''".to_string(),
+    Value::Binary(bytes) | Value::Geometry(bytes) => {
+      let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+      format!("X'{}'", hex)
+    }
+    other => quote_literal(&other.render().unwrap_or_default()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn json_floats_keep_their_shortest_form() {
+    assert_eq!(json_value(&Value::Float(1.1)).to_string(), "1.1");
+    assert_eq!(json_value(&Value::Float(-0.3)).to_string(), "-0.3");
+    assert_eq!(json_value(&Value::Float(f32::NAN)), serde_json::Value::Null);
+    assert_eq!(json_value(&Value::Double(0.1)).to_string(), "0.1");
+    assert_eq!(
+      json_value(&Value::Binary(vec![0xff, 0x00, b'a'])),
+      serde_json::Value::String("/wBh".into())
+    );
+  }
+
+  #[test]
+  fn sql_literals() {
+    assert_eq!(sql_literal(&Value::Null), "NULL");
+    assert_eq!(sql_literal(&Value::Int(-7)), "-7");
+    assert_eq!(sql_literal(&Value::UInt(u64::MAX)), "18446744073709551615");
+    assert_eq!(sql_literal(&Value::Decimal("0.10".to_string())), "0.10");
+    assert_eq!(sql_literal(&Value::Text("it's".to_string())), "'it\\'s'");
+    assert_eq!(sql_literal(&Value::Binary(vec![0, 0xab])), "X'00AB'");
+    assert_eq!(sql_literal(&Value::Binary(Vec::new())), "''");
+    assert_eq!(
+      sql_literal(&Value::Date(
+        chrono::NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()
+      )),
+      "'2024-02-29'"
+    );
+  }
+
+  #[test]
+  fn ddl_types() {
+    assert_eq!(ddl_type("BOOLEAN"), "TINYINT(1)");
+    assert_eq!(ddl_type("BIGINT UNSIGNED"), "BIGINT UNSIGNED");
+    assert_eq!(ddl_type("DECIMAL"), "DECIMAL(65,30)");
+    assert_eq!(ddl_type("DECIMAL(10,2)"), "DECIMAL(10,2)");
+    assert_eq!(ddl_type("DATETIME"), "DATETIME(6)");
+    assert_eq!(ddl_type("VARBINARY"), "LONGBLOB");
+    assert_eq!(ddl_type("VARCHAR"), "LONGTEXT");
+    assert_eq!(ddl_type("ENUM"), "LONGTEXT");
+  }
+}
@@ -0,0 +1,172 @@
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
+
+use chrono::Local;
+use futures::future::try_join_all;
+use log::{info, warn};
+use serde::Serialize;
+use sqlx::{
+  mysql::{MySqlConnectOptions, MySqlPoolOptions},
+  Executor, MySqlPool, Row,
+};
+
+/// Point in time every table of a `--snapshot` export reflects, saved as `{output}/metadata.json`
+#[derive(Debug, Default, Serialize)]
+pub struct Metadata {
+  /// local time the snapshot was taken
+  pub started_at: String,
+  /// current binary log file, unset when binary logging is disabled
+  pub binlog_file: Option<String>,
+  pub binlog_position: Option<u64>,
+  /// `gtid_executed` at the snapshot, empty when GTIDs are off
+  pub gtid_executed: Option<String>,
+}
+
+/// Opens a pool of `workers` connections that all share one consistent snapshot.
+///
+/// Like mysqldump and mydumper, a global read lock is held on a connection of `pool`
+/// while every worker starts `WITH CONSISTENT SNAPSHOT` and the binlog coordinates are
+/// read, so the workers and the recorded position agree. The returned pool refuses to
+/// open further connections since they could not join the snapshot.
+pub async fn open(
+  pool: &MySqlPool,
+  pool_options: MySqlPoolOptions,
+  options: &MySqlConnectOptions,
+  workers: u32,
+  output: &str,
+) -> Result<MySqlPool, Box<dyn std::error::Error>> {
+  let mut lock = pool.acquire().await?;
+  let locked = match lock.execute("FLUSH TABLES WITH READ LOCK").await {
+    Ok(_) => true,
+    Err(err) if workers == 1 => {
+      warn!(
+        "Could not take a global read lock ({}), the binlog position may not match the snapshot",
+        err
+      );
+      false
+    }
+    Err(err) => {
+      return Err(
+        format!(
+          "--snapshot with several workers needs a global read lock: {}",
+          err
+        )
+        .into(),
+      )
+    }
+  };
+
+  let sealed = Arc::new(AtomicBool::new(false));
+  let snapshot_pool = pool_options
+    .max_connections(workers)
+    .idle_timeout(None)
+    .max_lifetime(None)
+    .after_connect({
+      let sealed = sealed.clone();
+      move |conn, _meta| {
+        let sealed = sealed.clone();
+        Box::pin(async move {
+          if sealed.load(Ordering::SeqCst) {
+            return Err(sqlx::Error::Protocol(
+              "snapshot connection lost, a new connection cannot join the snapshot".into(),
+            ));
+          }
+          conn
+            .execute("SET SESSION TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+            .await?;
+          conn
+            .execute("START TRANSACTION WITH CONSISTENT SNAPSHOT, READ ONLY")
+            .await?;
+          Ok(())
+        })
+      }
+    })
+    .connect_lazy_with(options.clone());
+
+  // open every worker connection while the lock is held, then hand them back to the pool
+  let conns = try_join_all((0..workers).map(|_| snapshot_pool.acquire())).await?;
+  sealed.store(true, Ordering::SeqCst);
+
+  let mut metadata = Metadata {
+    started_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    ..Default::default()
+  };
+  // MySQL 8.4 removed SHOW MASTER STATUS in favour of SHOW BINARY LOG STATUS
+  let status = match lock.fetch_optional("SHOW BINARY LOG STATUS").await {
+    Ok(status) => status,
+    Err(_) => lock.fetch_optional("SHOW MASTER STATUS").await?,
+  };
+  if let Some(row) = status {
+    metadata.binlog_file = row.try_get_unchecked::<String, _>(0).ok();
+    metadata.binlog_position = row.try_get_unchecked::<u64, _>(1).ok();
+    metadata.gtid_executed = row
+      .try_get_unchecked::<String, _>(4)
+      .ok()
+      .filter(|gtid| !gtid.is_empty());
+  }
+
+  if locked {
+    lock.execute("UNLOCK TABLES").await?;
+  }
+  drop(lock);
+  drop(conns);
+
+  std::fs::write(
+    format!("{}/metadata.json", output),
+    serde_json::to_vec_pretty(&metadata)?,
+  )?;
+  info!(
+    "Consistent snapshot on {} connections at {}:{}",
+    workers,
+    metadata.binlog_file.as_deref().unwrap_or("-"),
+    metadata
+      .binlog_position
+      .map_or_else(|| "-".to_string(), |pos| pos.to_string())
+  );
+
+  Ok(snapshot_pool)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+
+  #[test]
+  fn metadata_keeps_unknown_coordinates_as_null() {
+    let metadata = Metadata {
+      started_at: "2024-01-02 03:04:05".to_string(),
+      binlog_file: Some("binlog.000042".to_string()),
+      binlog_position: Some(157),
+      gtid_executed: None,
+    };
+    assert_eq!(
+      serde_json::to_value(&metadata).unwrap(),
+      serde_json::json!({
+        "started_at": "2024-01-02 03:04:05",
+        "binlog_file": "binlog.000042",
+        "binlog_position": 157,
+        "gtid_executed": null,
+      })
+    );
+  }
+
+  #[tokio::test]
+  async fn writes_no_metadata_without_a_server() {
+    let output = std::env::temp_dir().join(format!("mysql2csv-{}-snapshot", std::process::id()));
+    std::fs::create_dir_all(&output).unwrap();
+    let options: MySqlConnectOptions = "mysql://root@127.0.0.1:1/shop".parse().unwrap();
+    let pool_options = MySqlPoolOptions::new().acquire_timeout(Duration::from_millis(500));
+    let pool = pool_options.clone().connect_lazy_with(options.clone());
+
+    let result = open(&pool, pool_options, &options, 2, &output.to_string_lossy()).await;
+    let written = output.join("metadata.json").exists();
+    std::fs::remove_dir_all(&output).unwrap();
+
+    assert!(result.is_err());
+    assert!(!written);
+  }
+}
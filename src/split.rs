@@ -0,0 +1,262 @@
+use log::info;
+use serde::Serialize;
+
+use crate::{
+  export::Export,
+  sink::{self, Sink},
+  value::Value,
+};
+
+/// One entry of the `{stem}.index.json` written next to the parts
+#[derive(Debug, Serialize)]
+struct Part {
+  file: String,
+  rows: u64,
+}
+
+/// Rolls over to `{stem}_00001.{ext}`, `{stem}_00002.{ext}`, ... once a part reaches
+/// `--max-rows-per-file` rows or `--max-bytes-per-file` bytes (counted before compression).
+/// The byte limit is checked against what has left the write buffer, so a part can run over
+/// it by up to one buffer. Every part gets its own header, and an index of parts and row counts
+/// is written at the end.
+pub struct SplitSink<'a> {
+  export: &'a Export<'a>,
+  folder_path: String,
+  stem: String,
+  current: Option<Box<dyn Sink>>,
+  current_rows: u64,
+  parts: Vec<Part>,
+  header: bool,
+}
+
+impl<'a> SplitSink<'a> {
+  pub fn new(export: &'a Export<'a>, folder_path: &str, stem: &str) -> Self {
+    SplitSink {
+      export,
+      folder_path: folder_path.to_string(),
+      stem: stem.to_string(),
+      current: None,
+      current_rows: 0,
+      parts: Vec::new(),
+      header: false,
+    }
+  }
+
+  fn is_full(&self, sink: &dyn Sink) -> std::io::Result<bool> {
+    let cli = self.export.cli;
+    if cli
+      .max_rows_per_file
+      .is_some_and(|max| self.current_rows >= max)
+    {
+      return Ok(true);
+    }
+    match cli.max_bytes_per_file {
+      Some(max) => Ok(sink.offset()? >= max),
+      None => Ok(false),
+    }
+  }
+
+  fn close_part(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(sink) = self.current.take() {
+      sink.finish()?;
+      if let Some(part) = self.parts.last_mut() {
+        part.rows = self.current_rows;
+      }
+    }
+    Ok(())
+  }
+
+  fn open_part(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    let file = format!(
+      "{}_{:05}.{}",
+      self.stem,
+      self.parts.len() + 1,
+      sink::extension(self.export.cli)
+    );
+    let path = format!("{}/{}", self.folder_path, file);
+    let mut sink = sink::create(self.export, &path, None)?;
+    if self.header {
+      sink.write_header()?;
+    }
+    self.current = Some(sink);
+    self.current_rows = 0;
+    self.parts.push(Part { file, rows: 0 });
+    Ok(())
+  }
+}
+
+impl Sink for SplitSink<'_> {
+  fn write_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    self.header = true;
+    if let Some(sink) = self.current.as_mut() {
+      sink.write_header()?;
+    }
+    Ok(())
+  }
+
+  fn write_row(&mut self, row: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+    let full = match self.current.as_deref() {
+      Some(sink) => self.is_full(sink)?,
+      None => true,
+    };
+    if full {
+      self.close_part()?;
+      self.open_part()?;
+    }
+    if let Some(sink) = self.current.as_mut() {
+      sink.write_row(row)?;
+    }
+    self.current_rows += 1;
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(sink) = self.current.as_mut() {
+      sink.flush()?;
+    }
+    Ok(())
+  }
+
+  fn offset(&self) -> std::io::Result<u64> {
+    match self.current.as_ref() {
+      Some(sink) => sink.offset(),
+      None => Ok(0),
+    }
+  }
+
+  fn finish(mut self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+    if self.parts.is_empty() {
+      self.open_part()?;
+    }
+    self.close_part()?;
+
+    let index_path = format!("{}/{}.index.json", self.folder_path, self.stem);
+    std::fs::write(&index_path, serde_json::to_vec_pretty(&self.parts)?)?;
+    info!("Wrote {} parts, listed in {}", self.parts.len(), index_path);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+  use indicatif::ProgressBar;
+  use sqlx::mysql::MySqlPoolOptions;
+
+  use super::*;
+  use crate::{transform::Pipeline, Cli};
+
+  /// Writes `rows` single column rows through a `SplitSink` and returns the part files
+  /// with their contents, followed by the index
+  async fn split(name: &str, args: &[&str], rows: &[&str]) -> (Vec<(String, String)>, String) {
+    let folder = std::env::temp_dir().join(format!("mysql2csv-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&folder).unwrap();
+    let folder_path = folder.to_string_lossy().into_owned();
+    let cli = Cli::parse_from(
+      ["mysql2csv", "-D", "shop", "-t", "orders"]
+        .iter()
+        .chain(args),
+    );
+    let pool = MySqlPoolOptions::new()
+      .connect_lazy("mysql://root@127.0.0.1:1/shop")
+      .unwrap();
+    let pipeline = Pipeline::new(&[], "", &[], None, &[]).unwrap();
+    let pb = ProgressBar::hidden();
+    let types = ["VARCHAR".to_string()];
+    let export = Export {
+      pool: &pool,
+      cli: &cli,
+      query_col_name: &["note"],
+      query_col_type: &[],
+      projection: &[0],
+      vec_col_name: &["note"],
+      vec_col_type_name: &types,
+      pipeline: &pipeline,
+      pb: &pb,
+    };
+
+    let mut sink: Box<dyn Sink> = Box::new(SplitSink::new(&export, &folder_path, "orders"));
+    sink.write_header().unwrap();
+    for row in rows {
+      sink.write_row(&[Value::Text(row.to_string())]).unwrap();
+    }
+    sink.finish().unwrap();
+
+    let mut parts: Vec<(String, String)> = std::fs::read_dir(&folder)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+      .filter(|file| !file.ends_with(".index.json"))
+      .map(|file| {
+        let text = std::fs::read_to_string(folder.join(&file)).unwrap();
+        (file, text)
+      })
+      .collect();
+    parts.sort();
+    let index = std::fs::read_to_string(folder.join("orders.index.json")).unwrap();
+    std::fs::remove_dir_all(&folder).unwrap();
+    (parts, index)
+  }
+
+  #[tokio::test]
+  async fn rolls_over_by_rows() {
+    let (parts, index) = split(
+      "split-rows",
+      &["--max-rows-per-file", "2"],
+      &["a", "b", "c", "d", "e"],
+    )
+    .await;
+
+    assert_eq!(
+      parts,
+      [
+        ("orders_00001.csv".to_string(), "note\na\nb\n".to_string()),
+        ("orders_00002.csv".to_string(), "note\nc\nd\n".to_string()),
+        ("orders_00003.csv".to_string(), "note\ne\n".to_string()),
+      ]
+    );
+    let index: serde_json::Value = serde_json::from_str(&index).unwrap();
+    assert_eq!(
+      index,
+      serde_json::json!([
+        {"file": "orders_00001.csv", "rows": 2},
+        {"file": "orders_00002.csv", "rows": 2},
+        {"file": "orders_00003.csv", "rows": 1},
+      ])
+    );
+  }
+
+  #[tokio::test]
+  async fn rolls_over_by_bytes() {
+    let row = "x".repeat(999);
+    let (parts, _) = split(
+      "split-bytes",
+      &["--max-bytes-per-file", "20000"],
+      &vec![row.as_str(); 100],
+    )
+    .await;
+
+    assert!(parts.len() > 2);
+    for (num, (file, text)) in parts.iter().enumerate() {
+      assert_eq!(file, &format!("orders_{:05}.csv", num + 1));
+      assert!(text.starts_with("note\n"));
+      // the limit is checked against what left the write buffer
+      assert!(text.len() < 20_000 + 8_192 + 1_000);
+      if num + 1 < parts.len() {
+        assert!(text.len() >= 20_000);
+      }
+    }
+    let rows: usize = parts.iter().map(|(_, text)| text.lines().count() - 1).sum();
+    assert_eq!(rows, 100);
+  }
+
+  #[tokio::test]
+  async fn writes_an_empty_part_without_rows() {
+    let (parts, index) = split("split-empty", &["--max-rows-per-file", "2"], &[]).await;
+
+    assert_eq!(
+      parts,
+      [("orders_00001.csv".to_string(), "note\n".to_string())]
+    );
+    assert!(index.contains("\"rows\": 0"));
+  }
+}
@@ -0,0 +1,62 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Reads a JSON state file such as a checkpoint or watermark, returns `None` when there is none on disk
+pub fn load<T: DeserializeOwned>(path: &str) -> Result<Option<T>, Box<dyn std::error::Error>> {
+  match std::fs::read_to_string(path) {
+    Ok(text) => Ok(Some(
+      serde_json::from_str(&text).map_err(|err| format!("cannot parse {}: {}", path, err))?,
+    )),
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+    Err(err) => Err(Box::new(err)),
+  }
+}
+
+/// Writes a JSON state file atomically via a temporary file
+pub fn save<T: Serialize>(state: &T, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+  let tmp_path = format!("{}.tmp", path);
+  std::fs::write(&tmp_path, serde_json::to_vec_pretty(state)?)?;
+  std::fs::rename(&tmp_path, path)?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use super::*;
+
+  fn temp_path(name: &str) -> String {
+    std::env::temp_dir()
+      .join(format!("mysql2csv-{}-{}", std::process::id(), name))
+      .to_string_lossy()
+      .into_owned()
+  }
+
+  #[test]
+  fn round_trips_and_replaces() {
+    let path = temp_path("round.state.json");
+    assert!(load::<HashMap<String, u64>>(&path).unwrap().is_none());
+
+    save(&HashMap::from([("rows".to_string(), 1u64)]), &path).unwrap();
+    save(&HashMap::from([("rows".to_string(), 2u64)]), &path).unwrap();
+    let loaded = load::<HashMap<String, u64>>(&path).unwrap();
+    let leftover = std::path::Path::new(&format!("{}.tmp", path)).exists();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded, Some(HashMap::from([("rows".to_string(), 2)])));
+    assert!(!leftover);
+  }
+
+  #[test]
+  fn reports_the_broken_file() {
+    let path = temp_path("broken.state.json");
+    std::fs::write(&path, "{").unwrap();
+    let err = load::<HashMap<String, u64>>(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(err
+      .to_string()
+      .starts_with(&format!("cannot parse {}", path)));
+  }
+}
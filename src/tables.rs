@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use sqlx::MySqlPool;
+
+/// Translates a shell-style name pattern (`*`, `?`) into an anchored regex
+pub fn glob_regex(pattern: &str) -> Result<Regex, regex::Error> {
+  let mut re = String::from("^");
+  for ch in pattern.chars() {
+    match ch {
+      '*' => re.push_str(".*"),
+      '?' => re.push('.'),
+      ch => re.push_str(&regex::escape(&ch.to_string())),
+    }
+  }
+  re.push('$');
+  Regex::new(&re)
+}
+
+/// Lists the base tables of `db` that match any `include` pattern (all when empty)
+/// and no `exclude` pattern, in name order
+pub async fn discover(
+  pool: &MySqlPool,
+  db: &str,
+  include: &[String],
+  exclude: &[String],
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+  let include = include
+    .iter()
+    .map(|pattern| glob_regex(pattern))
+    .collect::<Result<Vec<_>, _>>()?;
+  let exclude = exclude
+    .iter()
+    .map(|pattern| glob_regex(pattern))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let names: Vec<String> = sqlx::query_scalar(
+    "SELECT CAST(TABLE_NAME AS CHAR) FROM information_schema.TABLES \
+     WHERE TABLE_SCHEMA = ? AND TABLE_TYPE = 'BASE TABLE' ORDER BY TABLE_NAME",
+  )
+  .bind(db)
+  .fetch_all(pool)
+  .await?;
+
+  Ok(select(names, &include, &exclude))
+}
+
+fn select(names: Vec<String>, include: &[Regex], exclude: &[Regex]) -> Vec<String> {
+  names
+    .into_iter()
+    .filter(|name| include.is_empty() || include.iter().any(|re| re.is_match(name)))
+    .filter(|name| !exclude.iter().any(|re| re.is_match(name)))
+    .collect()
+}
+
+/// `DECIMAL(p,s)` type names of the DECIMAL columns of `db`.`table`, by column name.
+/// The result metadata of a query has no precision or scale, so they come from here.
+pub async fn decimal_types(
+  pool: &MySqlPool,
+  db: &str,
+  table: &str,
+) -> Result<HashMap<String, String>, sqlx::Error> {
+  let rows: Vec<(String, u64, u64)> = sqlx::query_as(
+    "SELECT CAST(COLUMN_NAME AS CHAR), CAST(NUMERIC_PRECISION AS UNSIGNED), \
+     CAST(NUMERIC_SCALE AS UNSIGNED) FROM information_schema.COLUMNS \
+     WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ? AND DATA_TYPE = 'decimal'",
+  )
+  .bind(db)
+  .bind(table)
+  .fetch_all(pool)
+  .await?;
+
+  Ok(
+    rows
+      .into_iter()
+      .map(|(name, precision, scale)| (name, format!("DECIMAL({},{})", precision, scale)))
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn patterns(globs: &[&str]) -> Vec<Regex> {
+    globs.iter().map(|glob| glob_regex(glob).unwrap()).collect()
+  }
+
+  #[test]
+  fn globs_match_whole_names() {
+    let re = glob_regex("log_?.*").unwrap();
+    assert!(re.is_match("log_1.old"));
+    assert!(!re.is_match("log_12.old"));
+    assert!(!re.is_match("log_1xold"));
+    assert!(!re.is_match("audit_log_1.old"));
+    assert!(glob_regex("*").unwrap().is_match(""));
+  }
+
+  #[test]
+  fn excludes_win_over_includes() {
+    let names = ["audit_log", "orders", "orders_2023", "users"].map(String::from);
+    assert_eq!(select(names.to_vec(), &[], &[]), names);
+    assert_eq!(
+      select(
+        names.to_vec(),
+        &patterns(&["orders*", "users"]),
+        &patterns(&["*_20??"])
+      ),
+      ["orders", "users"]
+    );
+    assert_eq!(
+      select(names.to_vec(), &[], &patterns(&["*log*"])),
+      ["orders", "orders_2023", "users"]
+    );
+  }
+}
@@ -0,0 +1,91 @@
+use std::io::{self, BufRead, Write};
+
+/// Prints `prompt` on stderr and reads a line from stdin without echoing it
+pub fn read_hidden(prompt: &str) -> io::Result<String> {
+  eprint!("{}", prompt);
+  io::stderr().flush()?;
+
+  let mut line = String::new();
+  {
+    let _echo_off = EchoOff::new()?;
+    io::stdin().lock().read_line(&mut line)?;
+  }
+  eprintln!();
+
+  Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Turns off echo of the terminal on stdin, restoring the previous mode when dropped,
+/// including when reading the input fails or panics
+pub struct EchoOff {
+  #[cfg(unix)]
+  saved: libc::termios,
+  #[cfg(windows)]
+  saved: u32,
+}
+
+#[cfg(unix)]
+impl EchoOff {
+  pub fn new() -> io::Result<Self> {
+    // SAFETY: termios is plain data filled in by tcgetattr before it is read
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+      return Err(io::Error::last_os_error());
+    }
+    let mut silent = saved;
+    silent.c_lflag &= !libc::ECHO;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &silent) } != 0 {
+      return Err(io::Error::last_os_error());
+    }
+
+    Ok(EchoOff { saved })
+  }
+}
+
+#[cfg(unix)]
+impl Drop for EchoOff {
+  fn drop(&mut self) {
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+  }
+}
+
+#[cfg(windows)]
+mod console {
+  pub type Handle = *mut std::ffi::c_void;
+  pub const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+  pub const ENABLE_ECHO_INPUT: u32 = 0x0004;
+
+  #[link(name = "kernel32")]
+  extern "system" {
+    pub fn GetStdHandle(std_handle: u32) -> Handle;
+    pub fn GetConsoleMode(console: Handle, mode: *mut u32) -> i32;
+    pub fn SetConsoleMode(console: Handle, mode: u32) -> i32;
+  }
+}
+
+#[cfg(windows)]
+impl EchoOff {
+  pub fn new() -> io::Result<Self> {
+    let mut saved = 0;
+    // SAFETY: the standard input handle stays valid for the life of the process
+    unsafe {
+      let handle = console::GetStdHandle(console::STD_INPUT_HANDLE);
+      if console::GetConsoleMode(handle, &mut saved) == 0
+        || console::SetConsoleMode(handle, saved & !console::ENABLE_ECHO_INPUT) == 0
+      {
+        return Err(io::Error::last_os_error());
+      }
+    }
+
+    Ok(EchoOff { saved })
+  }
+}
+
+#[cfg(windows)]
+impl Drop for EchoOff {
+  fn drop(&mut self) {
+    unsafe {
+      console::SetConsoleMode(console::GetStdHandle(console::STD_INPUT_HANDLE), self.saved)
+    };
+  }
+}
@@ -0,0 +1,302 @@
+use std::fmt::Write;
+
+use chrono::{
+  format::{Item, StrftimeItems},
+  NaiveDate, NaiveDateTime,
+};
+use regex::Regex;
+
+use crate::{
+  mask::{self, Mask, MaskRule},
+  value::Value,
+};
+
+/// One step of a column pipeline. Text steps work on the rendered value of any type
+/// and turn it into text, NULL passes through all of them except `default`.
+#[derive(Debug, Clone)]
+pub enum Op {
+  Replace(Regex, String),
+  Trim,
+  /// remove every control character, newlines and tabs included
+  StripControl,
+  /// replace CR and LF with a space
+  StripNewlines,
+  Upper,
+  Lower,
+  /// keep at most this many characters
+  Truncate(usize),
+  /// text written instead of NULL
+  Default(String),
+  /// reformat dates and datetimes, including text in `YYYY-MM-DD[ HH:MM:SS]` form,
+  /// with a chrono format string
+  Date(String),
+}
+
+/// `--transform COLUMN:OP[=ARG]`, `*` applies to every column
+#[derive(Debug, Clone)]
+pub struct Transform {
+  pub column: String,
+  pub op: Op,
+}
+
+/// Parses a `--transform` value such as `remark:replace=/\|//`, `name:trim` or `created:date=%d/%m/%Y`.
+/// The first character after `replace=` delimits pattern and replacement, like sed.
+pub fn parse_transform(spec: &str) -> Result<Transform, String> {
+  let (column, op) = spec
+    .split_once(':')
+    .ok_or_else(|| format!("expected COLUMN:OP, got `{}`", spec))?;
+  let (name, arg) = match op.split_once('=') {
+    Some((name, arg)) => (name, Some(arg)),
+    None => (op, None),
+  };
+  let op = match (name, arg) {
+    ("replace", Some(arg)) => {
+      let mut chars = arg.chars();
+      let delim = chars.next().ok_or("replace needs /PATTERN/REPLACEMENT/")?;
+      let parts: Vec<&str> = chars.as_str().split(delim).collect();
+      match parts.as_slice() {
+        [pattern, replacement] | [pattern, replacement, ""] => Op::Replace(
+          Regex::new(pattern).map_err(|err| err.to_string())?,
+          replacement.to_string(),
+        ),
+        _ => {
+          return Err(format!(
+            "replace needs {}PATTERN{}REPLACEMENT{}",
+            delim, delim, delim
+          ))
+        }
+      }
+    }
+    ("trim", None) => Op::Trim,
+    ("strip-control", None) => Op::StripControl,
+    ("strip-newlines", None) => Op::StripNewlines,
+    ("upper", None) => Op::Upper,
+    ("lower", None) => Op::Lower,
+    ("truncate", Some(len)) => Op::Truncate(
+      len
+        .parse()
+        .map_err(|_| format!("truncate needs a length, got `{}`", len))?,
+    ),
+    ("default", Some(text)) => Op::Default(text.to_string()),
+    ("date", Some(format)) => Op::Date(parse_date_format(format)?),
+    _ => return Err(format!("unknown transform `{}`", op)),
+  };
+
+  Ok(Transform {
+    column: column.to_string(),
+    op,
+  })
+}
+
+/// Checks a `date=` format up front: chrono panics while rendering unknown specifiers,
+/// and time zone specifiers have nothing to show for the naive values MySQL returns
+fn parse_date_format(format: &str) -> Result<String, String> {
+  if StrftimeItems::new(format).any(|item| item == Item::Error) {
+    return Err(format!("invalid date format `{}`", format));
+  }
+  let mut probe = String::new();
+  write!(probe, "{}", NaiveDateTime::default().format(format))
+    .map_err(|_| format!("date format `{}` needs a time zone", format))?;
+  Ok(format.to_string())
+}
+
+impl Op {
+  fn apply(&self, value: Value) -> Value {
+    match (self, value) {
+      (Op::Default(text), Value::Null) => Value::Text(text.clone()),
+      (_, Value::Null) => Value::Null,
+      (Op::Default(_), value) => value,
+      (Op::Date(format), Value::Date(date)) => {
+        Value::Text(date.and_time(Default::default()).format(format).to_string())
+      }
+      (Op::Date(format), Value::DateTime(datetime)) => {
+        Value::Text(datetime.format(format).to_string())
+      }
+      (Op::Date(format), Value::Text(text)) => {
+        let trimmed = text.trim();
+        match NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%d %H:%M:%S%.f") {
+          Ok(datetime) => Value::Text(datetime.format(format).to_string()),
+          Err(_) => match NaiveDate::parse_from_str(trimmed, "%Y-%m-%d") {
+            Ok(date) => Value::Text(date.and_time(Default::default()).format(format).to_string()),
+            Err(_) => Value::Text(text),
+          },
+        }
+      }
+      (Op::Date(_), value) => value,
+      (op, value) => {
+        let text = match value {
+          Value::Text(text) => text,
+          value => value.render().unwrap_or_default(),
+        };
+        Value::Text(match op {
+          Op::Replace(re, replacement) => re.replace_all(&text, replacement.as_str()).into_owned(),
+          Op::Trim => text.trim().to_string(),
+          Op::StripControl => text.chars().filter(|c| !c.is_control()).collect(),
+          Op::StripNewlines => text.replace("\r\n", " ").replace(['\r', '\n'], " "),
+          Op::Upper => text.to_uppercase(),
+          Op::Lower => text.to_lowercase(),
+          Op::Truncate(len) => text.chars().take(*len).collect(),
+          Op::Default(_) | Op::Date(_) => text,
+        })
+      }
+    }
+  }
+}
+
+/// Transforms resolved to result columns, applied in the order they were given,
+/// followed by the first `--mask` rule matching the column name
+#[derive(Debug, Default)]
+pub struct Pipeline {
+  columns: Vec<Vec<Op>>,
+  masks: Vec<Option<MaskRule>>,
+  key: Option<Vec<u8>>,
+}
+
+impl Pipeline {
+  /// `--repcol` is kept as shorthand for removing `|` from one column
+  pub fn new(
+    transforms: &[Transform],
+    repcol: &str,
+    masks: &[Mask],
+    key: Option<Vec<u8>>,
+    names: &[&str],
+  ) -> Result<Self, Box<dyn std::error::Error>> {
+    let mut columns = vec![Vec::new(); names.len()];
+    if let Some(pos) = names.iter().position(|name| *name == repcol) {
+      columns[pos].push(Op::Replace(Regex::new(r"\|")?, String::new()));
+    }
+    for transform in transforms {
+      if transform.column == "*" {
+        for ops in columns.iter_mut() {
+          ops.push(transform.op.clone());
+        }
+        continue;
+      }
+      let pos = names
+        .iter()
+        .position(|name| *name == transform.column)
+        .ok_or_else(|| {
+          format!(
+            "transform column `{}` is not in the query result",
+            transform.column
+          )
+        })?;
+      columns[pos].push(transform.op.clone());
+    }
+
+    let masks = names
+      .iter()
+      .map(|name| {
+        masks
+          .iter()
+          .find(|mask| mask.pattern.is_match(name))
+          .map(|mask| mask.rule.clone())
+      })
+      .collect();
+
+    Ok(Pipeline {
+      columns,
+      masks,
+      key,
+    })
+  }
+
+  /// Whether column `num` has any step, which leaves its values as text
+  pub fn rewrites(&self, num: usize) -> bool {
+    !self.columns[num].is_empty() || self.masks[num].is_some()
+  }
+
+  pub fn masks(&self, num: usize) -> bool {
+    self.masks[num].is_some()
+  }
+
+  pub fn apply(&self, num: usize, value: Value) -> Value {
+    let value = self.columns[num]
+      .iter()
+      .fold(value, |value, op| op.apply(value));
+    match &self.masks[num] {
+      Some(rule) => mask::apply(rule, self.key.as_deref(), value),
+      None => value,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn op(spec: &str) -> Op {
+    parse_transform(spec).unwrap().op
+  }
+
+  fn text(value: &str) -> Value {
+    Value::Text(value.to_string())
+  }
+
+  #[test]
+  fn parses_transforms() {
+    let transform = parse_transform("remark:replace=#a/b#c#").unwrap();
+    assert_eq!(transform.column, "remark");
+    assert_eq!(transform.op.apply(text("xa/by")), text("xcy"));
+    assert_eq!(op(r"remark:replace=/\|/").apply(text("a|b")), text("ab"));
+    assert!(matches!(op("name:trim"), Op::Trim));
+    assert!(matches!(op("name:truncate=3"), Op::Truncate(3)));
+    assert!(matches!(op("name:default=n/a"), Op::Default(text) if text == "n/a"));
+    assert!(matches!(op("created:date=%d/%m/%Y"), Op::Date(format) if format == "%d/%m/%Y"));
+    assert_eq!(parse_transform("*:upper").unwrap().column, "*");
+  }
+
+  #[test]
+  fn rejects_bad_transforms() {
+    assert!(parse_transform("name").is_err());
+    assert!(parse_transform("name:shout").is_err());
+    assert!(parse_transform("name:trim=1").is_err());
+    assert!(parse_transform("name:truncate=many").is_err());
+    assert!(parse_transform("name:replace=").is_err());
+    assert!(parse_transform("name:replace=/a/b/c/").is_err());
+    assert!(parse_transform("name:replace=/(/x/").is_err());
+    assert!(parse_transform("created:date=%Q").is_err());
+    assert!(parse_transform("created:date=%Y-%").is_err());
+    assert!(parse_transform("created:date=%Y %z").is_err());
+  }
+
+  #[test]
+  fn applies_ops() {
+    assert_eq!(Op::Trim.apply(Value::Null), Value::Null);
+    assert_eq!(op("name:default=-").apply(Value::Null), text("-"));
+    assert_eq!(op("name:default=-").apply(Value::Int(1)), Value::Int(1));
+    assert_eq!(Op::Upper.apply(Value::Int(5)), text("5"));
+    assert_eq!(Op::StripNewlines.apply(text("a\r\nb\nc")), text("a b c"));
+    assert_eq!(Op::StripControl.apply(text("a\tb\u{7}")), text("ab"));
+    assert_eq!(Op::Truncate(2).apply(text("héllo")), text("hé"));
+
+    let date = op("created:date=%d/%m/%Y");
+    assert_eq!(date.apply(text("2024-03-01 10:20:30")), text("01/03/2024"));
+    assert_eq!(date.apply(text("2024-03-01")), text("01/03/2024"));
+    assert_eq!(date.apply(text("0000-00-00")), text("0000-00-00"));
+    let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+    assert_eq!(date.apply(Value::Date(day)), text("01/03/2024"));
+    assert_eq!(date.apply(Value::Int(3)), Value::Int(3));
+    let time = op("created:date=%Y %H:%M");
+    assert_eq!(time.apply(Value::Date(day)), text("2024 00:00"));
+  }
+
+  #[test]
+  fn pipeline_runs_ops_in_order_then_masks() {
+    let transforms = [
+      parse_transform("name:trim").unwrap(),
+      parse_transform("*:upper").unwrap(),
+    ];
+    let masks = [mask::parse_mask("code:partial=1").unwrap()];
+    let pipeline =
+      Pipeline::new(&transforms, "note", &masks, None, &["name", "note", "code"]).unwrap();
+    assert_eq!(pipeline.apply(0, text(" ann ")), text("ANN"));
+    assert_eq!(pipeline.apply(1, text("a|b")), text("AB"));
+    assert_eq!(pipeline.apply(2, text("ab")), text("*B"));
+    assert!(pipeline.rewrites(0) && !pipeline.masks(0));
+    assert!(pipeline.masks(2));
+
+    let missing = [parse_transform("other:trim").unwrap()];
+    assert!(Pipeline::new(&missing, "", &[], None, &["name"]).is_err());
+  }
+}
@@ -0,0 +1,341 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use sqlx::{
+  mysql::{
+    types::{MySqlTime, MySqlTimeSign},
+    MySql, MySqlRow, MySqlTypeInfo,
+  },
+  Row, Type, TypeInfo,
+};
+
+/// How a result column is decoded, derived from its `MySqlTypeInfo`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+  Int,
+  UInt,
+  Float,
+  Double,
+  Decimal,
+  Bit,
+  Date,
+  Time,
+  DateTime,
+  Text,
+  Binary,
+  Json,
+  Geometry,
+  Null,
+}
+
+impl ColumnKind {
+  pub fn from_type_info(type_info: &MySqlTypeInfo) -> Self {
+    match type_name(type_info) {
+      "BOOLEAN" | "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "BIGINT" => ColumnKind::Int,
+      "TINYINT UNSIGNED" | "SMALLINT UNSIGNED" | "MEDIUMINT UNSIGNED" | "INT UNSIGNED"
+      | "BIGINT UNSIGNED" | "YEAR" => ColumnKind::UInt,
+      "FLOAT" => ColumnKind::Float,
+      "DOUBLE" => ColumnKind::Double,
+      "DECIMAL" => ColumnKind::Decimal,
+      "BIT" => ColumnKind::Bit,
+      "DATE" => ColumnKind::Date,
+      "TIME" => ColumnKind::Time,
+      "DATETIME" | "TIMESTAMP" => ColumnKind::DateTime,
+      "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB" => {
+        ColumnKind::Binary
+      }
+      "JSON" => ColumnKind::Json,
+      "GEOMETRY" => ColumnKind::Geometry,
+      "NULL" => ColumnKind::Null,
+      // CHAR, VARCHAR, TEXT variants, ENUM and SET
+      _ => ColumnKind::Text,
+    }
+  }
+}
+
+/// Server type name of a column. sqlx names every `TINYINT(1)` BOOLEAN, the unsigned
+/// ones are reported as `TINYINT UNSIGNED` so they decode and map as unsigned integers.
+pub fn type_name(type_info: &MySqlTypeInfo) -> &str {
+  match type_info.name() {
+    "BOOLEAN" if <u8 as Type<MySql>>::compatible(type_info) => "TINYINT UNSIGNED",
+    name => name,
+  }
+}
+
+/// A single decoded cell
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+  Null,
+  Int(i64),
+  UInt(u64),
+  Float(f32),
+  Double(f64),
+  /// DECIMAL is kept in the textual form sent by the server, so precision up to 65 digits survives
+  Decimal(String),
+  Date(NaiveDate),
+  Time(MySqlTime),
+  DateTime(NaiveDateTime),
+  Text(String),
+  Binary(Vec<u8>),
+  Json(String),
+  Geometry(Vec<u8>),
+}
+
+impl Value {
+  /// Decodes column `index` of `row` according to `kind`
+  pub fn decode(row: &MySqlRow, index: usize, kind: ColumnKind) -> Result<Self, sqlx::Error> {
+    let value = match kind {
+      ColumnKind::Int => row.try_get::<Option<i64>, _>(index)?.map(Value::Int),
+      ColumnKind::UInt => row.try_get::<Option<u64>, _>(index)?.map(Value::UInt),
+      ColumnKind::Float => row.try_get::<Option<f32>, _>(index)?.map(Value::Float),
+      ColumnKind::Double => row.try_get::<Option<f64>, _>(index)?.map(Value::Double),
+      ColumnKind::Decimal => row
+        .try_get_unchecked::<Option<String>, _>(index)?
+        .map(Value::Decimal),
+      // BIT(M) arrives as M/8 big-endian bytes and may lack the UNSIGNED flag
+      ColumnKind::Bit => row
+        .try_get_unchecked::<Option<Vec<u8>>, _>(index)?
+        .map(|bytes| Value::UInt(bytes.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b)))),
+      // sqlx rejects TIMESTAMP as NaiveDateTime and zero dates altogether, so both are read raw
+      ColumnKind::Date | ColumnKind::DateTime => row
+        .try_get_unchecked::<Option<&[u8]>, _>(index)?
+        .map(|bytes| decode_datetime(bytes, kind == ColumnKind::DateTime))
+        .transpose()
+        .map_err(|source| sqlx::Error::ColumnDecode {
+          index: index.to_string(),
+          source: source.into(),
+        })?,
+      ColumnKind::Time => row.try_get::<Option<MySqlTime>, _>(index)?.map(Value::Time),
+      ColumnKind::Text => row
+        .try_get_unchecked::<Option<String>, _>(index)?
+        .map(Value::Text),
+      ColumnKind::Binary => row.try_get::<Option<Vec<u8>>, _>(index)?.map(Value::Binary),
+      ColumnKind::Json => row
+        .try_get_unchecked::<Option<String>, _>(index)?
+        .map(Value::Json),
+      ColumnKind::Geometry => row
+        .try_get_unchecked::<Option<Vec<u8>>, _>(index)?
+        .map(Value::Geometry),
+      ColumnKind::Null => None,
+    };
+
+    Ok(value.unwrap_or(Value::Null))
+  }
+
+  /// Renders the value as text, or `None` for NULL
+  pub fn render(&self) -> Option<String> {
+    let text = match self {
+      Value::Null => return None,
+      Value::Int(v) => v.to_string(),
+      Value::UInt(v) => v.to_string(),
+      Value::Float(v) => v.to_string(),
+      Value::Double(v) => v.to_string(),
+      Value::Decimal(v) | Value::Text(v) | Value::Json(v) => v.clone(),
+      Value::Date(v) => v.format("%Y-%m-%d").to_string(),
+      Value::Time(v) => format_time(v),
+      Value::DateTime(v) => v.format("%Y-%m-%d %H:%M:%S%.f").to_string(),
+      Value::Binary(v) => String::from_utf8_lossy(v).to_string(),
+      Value::Geometry(v) => v.iter().map(|b| format!("{:02X}", b)).collect(),
+    };
+
+    Some(text)
+  }
+}
+
+/// Decodes a DATE, DATETIME or TIMESTAMP cell. The binary protocol sends a length byte
+/// followed by year, month, day, hour, minute, second and microseconds, with trailing zero
+/// fields left out; the text protocol sends `YYYY-MM-DD[ HH:MM:SS[.ffffff]]`.
+/// Zero dates such as `0000-00-00` and partial ones such as `2024-05-00` have no chrono
+/// equivalent and are kept as text in the form MySQL prints them.
+fn decode_datetime(bytes: &[u8], with_time: bool) -> Result<Value, String> {
+  let (year, month, day, hour, minute, second, micros) = match bytes.first() {
+    Some(&len) if matches!(len, 0 | 4 | 7 | 11) && bytes.len() == usize::from(len) + 1 => {
+      let field = |pos: usize| u32::from(bytes.get(pos).copied().unwrap_or_default());
+      let micros = bytes
+        .get(8..12)
+        .map_or(0, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+      (
+        field(1) | (field(2) << 8),
+        field(3),
+        field(4),
+        field(5),
+        field(6),
+        field(7),
+        micros,
+      )
+    }
+    _ => {
+      let text = std::str::from_utf8(bytes).map_err(|err| err.to_string())?;
+      return Ok(parse_datetime_text(text, with_time));
+    }
+  };
+
+  let date = NaiveDate::from_ymd_opt(year as i32, month, day);
+  let time = NaiveTime::from_hms_micro_opt(hour, minute, second, micros);
+  Ok(match (date, time, with_time) {
+    (Some(date), _, false) => Value::Date(date),
+    (Some(date), Some(time), true) => Value::DateTime(date.and_time(time)),
+    (None, _, false) => Value::Text(format!("{:04}-{:02}-{:02}", year, month, day)),
+    _ => {
+      let mut text = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, hour, minute, second
+      );
+      if micros != 0 {
+        text.push_str(&format!(".{:06}", micros));
+      }
+      Value::Text(text)
+    }
+  })
+}
+
+fn parse_datetime_text(text: &str, with_time: bool) -> Value {
+  let parsed = if with_time {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f")
+      .ok()
+      .map(Value::DateTime)
+  } else {
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+      .ok()
+      .map(Value::Date)
+  };
+  parsed.unwrap_or_else(|| Value::Text(text.to_string()))
+}
+
+/// Formats TIME the way MySQL does: `[-]HH:MM:SS[.ffffff]`, hours may exceed 24
+fn format_time(time: &MySqlTime) -> String {
+  let sign = if time.sign() == MySqlTimeSign::Negative {
+    "-"
+  } else {
+    ""
+  };
+  let mut text = format!(
+    "{}{:02}:{:02}:{:02}",
+    sign,
+    time.hours(),
+    time.minutes(),
+    time.seconds()
+  );
+  if time.microseconds() != 0 {
+    text.push_str(&format!(".{:06}", time.microseconds()));
+  }
+
+  text
+}
+
+/// SQL literal of a key or watermark value, quoted unless numeric
+pub fn key_literal(value: &Value) -> Option<String> {
+  let text = value.render()?;
+  Some(match value {
+    Value::Int(_) | Value::UInt(_) => text,
+    _ => quote_literal(&text),
+  })
+}
+
+/// Quotes text as a MySQL string literal, escaping it the way `mysqldump` does
+pub fn quote_literal(text: &str) -> String {
+  let mut quoted = String::with_capacity(text.len() + 2);
+  quoted.push('\'');
+  for ch in text.chars() {
+    match ch {
+      '\0' => quoted.push_str("\\0"),
+      '\n' => quoted.push_str("\\n"),
+      '\r' => quoted.push_str("\\r"),
+      '\x1a' => quoted.push_str("\\Z"),
+      '\'' => quoted.push_str("\\'"),
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      ch => quoted.push(ch),
+    }
+  }
+  quoted.push('\'');
+
+  quoted
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn decodes_binary_datetimes() {
+    let midnight = NaiveDate::from_ymd_opt(2024, 5, 17).unwrap();
+    assert_eq!(
+      decode_datetime(&[4, 0xe8, 0x07, 5, 17], false),
+      Ok(Value::Date(midnight))
+    );
+    assert_eq!(
+      decode_datetime(&[4, 0xe8, 0x07, 5, 17], true),
+      Ok(Value::DateTime(midnight.and_hms_opt(0, 0, 0).unwrap()))
+    );
+    assert_eq!(
+      decode_datetime(
+        &[11, 0xe8, 0x07, 5, 17, 13, 4, 5, 0x40, 0xe2, 0x01, 0],
+        true
+      ),
+      Ok(Value::DateTime(
+        midnight.and_hms_micro_opt(13, 4, 5, 123_456).unwrap()
+      ))
+    );
+  }
+
+  #[test]
+  fn keeps_zero_dates_as_text() {
+    assert_eq!(
+      decode_datetime(&[0], false),
+      Ok(Value::Text("0000-00-00".to_string()))
+    );
+    assert_eq!(
+      decode_datetime(&[0], true),
+      Ok(Value::Text("0000-00-00 00:00:00".to_string()))
+    );
+    assert_eq!(
+      decode_datetime(&[7, 0xe8, 0x07, 5, 0, 10, 0, 0], true),
+      Ok(Value::Text("2024-05-00 10:00:00".to_string()))
+    );
+    assert_eq!(
+      decode_datetime(b"0000-00-00 00:00:00", true),
+      Ok(Value::Text("0000-00-00 00:00:00".to_string()))
+    );
+  }
+
+  #[test]
+  fn quotes_literals_like_mysqldump() {
+    assert_eq!(quote_literal("plain"), "'plain'");
+    assert_eq!(quote_literal("a'b\"c\\d"), "'a\\'b\\\"c\\\\d'");
+    assert_eq!(quote_literal("\0\n\r\x1a"), "'\\0\\n\\r\\Z'");
+    assert_eq!(quote_literal("ünï"), "'ünï'");
+  }
+
+  #[test]
+  fn unsigned_booleans_are_unsigned_integers() {
+    let boolean = <bool as Type<MySql>>::type_info();
+    assert_eq!(boolean.name(), "BOOLEAN");
+    assert_eq!(type_name(&boolean), "TINYINT UNSIGNED");
+    assert_eq!(ColumnKind::from_type_info(&boolean), ColumnKind::UInt);
+    let tinyint = <i8 as Type<MySql>>::type_info();
+    assert_eq!(ColumnKind::from_type_info(&tinyint), ColumnKind::Int);
+  }
+
+  #[test]
+  fn key_literals() {
+    assert_eq!(key_literal(&Value::Int(-3)).as_deref(), Some("-3"));
+    assert_eq!(key_literal(&Value::UInt(3)).as_deref(), Some("3"));
+    assert_eq!(
+      key_literal(&Value::Text("o'k".to_string())).as_deref(),
+      Some("'o\\'k'")
+    );
+    assert_eq!(key_literal(&Value::Null), None);
+  }
+
+  #[test]
+  fn decodes_text_datetimes() {
+    assert_eq!(
+      decode_datetime(b"2024-05-17 13:04:05.5", true),
+      Ok(Value::DateTime(
+        NaiveDate::from_ymd_opt(2024, 5, 17)
+          .unwrap()
+          .and_hms_milli_opt(13, 4, 5, 500)
+          .unwrap()
+      ))
+    );
+  }
+}
@@ -0,0 +1,304 @@
+use std::fs::File;
+
+use chrono::{NaiveDate, Timelike};
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use sqlx::mysql::types::MySqlTimeSign;
+
+use crate::{sink::Sink, value::Value};
+
+/// Rows per worksheet, including the header row
+const MAX_SHEET_ROWS: u32 = 1_048_576;
+
+/// Longest text Excel keeps in a single cell
+const MAX_CELL_CHARS: usize = 32_767;
+
+/// Integers beyond 15 digits lose precision as Excel numbers and are written as text
+const MAX_EXACT_INT: u64 = 999_999_999_999_999;
+
+/// Significant digits Excel keeps, longer DECIMALs are written as text
+const MAX_EXACT_DIGITS: usize = 15;
+
+/// Bytes counted for a numeric cell by `offset`
+const NUMBER_CELL_SIZE: u64 = 8;
+
+/// Cell formats of the header and of typed values
+struct Formats {
+  header: Format,
+  date: Format,
+  datetime: Format,
+  time: Format,
+  decimal: Format,
+}
+
+impl Formats {
+  fn new() -> Self {
+    Formats {
+      header: Format::new().set_bold(),
+      date: Format::new().set_num_format("yyyy-mm-dd"),
+      datetime: Format::new().set_num_format("yyyy-mm-dd hh:mm:ss"),
+      time: Format::new().set_num_format("[h]:mm:ss"),
+      decimal: Format::new().set_num_format("#,##0.00########"),
+    }
+  }
+}
+
+/// Excel workbook written with rust_xlsxwriter in constant memory mode: finished rows are
+/// streamed to temporary files and only the current row is held in memory, then `finish`
+/// assembles the package into the output file.
+/// A new worksheet is started, with its own header row, once a sheet is full.
+pub struct XlsxSink {
+  workbook: Workbook,
+  file: Option<File>,
+  formats: Formats,
+  names: Vec<String>,
+  sheets: usize,
+  sheet_rows: u32,
+  header: bool,
+  written: u64,
+}
+
+impl XlsxSink {
+  pub fn new(names: &[&str], file: File) -> Self {
+    XlsxSink {
+      workbook: Workbook::new(),
+      file: Some(file),
+      formats: Formats::new(),
+      names: names.iter().map(|name| name.to_string()).collect(),
+      sheets: 0,
+      sheet_rows: 0,
+      header: false,
+      written: 0,
+    }
+  }
+
+  fn open_sheet(&mut self) -> Result<(), XlsxError> {
+    self.workbook.add_worksheet_with_constant_memory();
+    self.sheets += 1;
+    self.sheet_rows = 0;
+    if self.header {
+      self.write_names()?;
+    }
+    Ok(())
+  }
+
+  fn write_names(&mut self) -> Result<(), XlsxError> {
+    let sheet = self.workbook.worksheet_from_index(self.sheets - 1)?;
+    for (col, name) in self.names.iter().enumerate() {
+      sheet.write_string_with_format(self.sheet_rows, col as u16, name, &self.formats.header)?;
+      self.written += name.len() as u64;
+    }
+    self.sheet_rows += 1;
+    Ok(())
+  }
+}
+
+impl Sink for XlsxSink {
+  fn write_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    self.header = true;
+    if self.sheets > 0 {
+      self.write_names()?;
+    }
+    Ok(())
+  }
+
+  fn write_row(&mut self, row: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+    if self.sheets == 0 || self.sheet_rows >= MAX_SHEET_ROWS {
+      self.open_sheet()?;
+    }
+    let sheet = self.workbook.worksheet_from_index(self.sheets - 1)?;
+    for (col, value) in row.iter().enumerate() {
+      self.written += write_cell(sheet, self.sheet_rows, col as u16, value, &self.formats)?;
+    }
+    self.sheet_rows += 1;
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+  }
+
+  /// Cell data written so far, the archive itself only exists once `finish` has run
+  fn offset(&self) -> std::io::Result<u64> {
+    Ok(self.written)
+  }
+
+  fn finish(mut self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+    if self.sheets == 0 {
+      self.open_sheet()?;
+    }
+    let file = self.file.take().ok_or("workbook already saved")?;
+    self.workbook.save_to_writer(file)?;
+    Ok(())
+  }
+}
+
+/// Writes one cell and returns the bytes it counts for; NULL cells are left out entirely
+fn write_cell(
+  sheet: &mut Worksheet,
+  row: u32,
+  col: u16,
+  value: &Value,
+  formats: &Formats,
+) -> Result<u64, XlsxError> {
+  match value {
+    Value::Null => return Ok(0),
+    Value::Int(v) if v.unsigned_abs() <= MAX_EXACT_INT => {
+      sheet.write_number(row, col, *v as f64)?;
+    }
+    Value::UInt(v) if *v <= MAX_EXACT_INT => {
+      sheet.write_number(row, col, *v as f64)?;
+    }
+    // widening to f64 would show 1.1 as 1.100000023841858, go through the shortest f32 text
+    Value::Float(v) if v.is_finite() => {
+      sheet.write_number(row, col, v.to_string().parse::<f64>().unwrap_or_default())?;
+    }
+    Value::Double(v) if v.is_finite() => {
+      sheet.write_number(row, col, *v)?;
+    }
+    Value::Decimal(v) if significant_digits(v) <= MAX_EXACT_DIGITS => {
+      let number = v.parse::<f64>().unwrap_or_default();
+      sheet.write_number_with_format(row, col, number, &formats.decimal)?;
+    }
+    Value::Date(v) if *v >= excel_min_date() => {
+      sheet.write_number_with_format(row, col, date_serial(v) as f64, &formats.date)?;
+    }
+    Value::DateTime(v) if v.date() >= excel_min_date() => {
+      let serial =
+        date_serial(&v.date()) as f64 + day_fraction(v.num_seconds_from_midnight(), v.nanosecond());
+      sheet.write_number_with_format(row, col, serial, &formats.datetime)?;
+    }
+    Value::Time(v) if v.sign() == MySqlTimeSign::Positive => {
+      let seconds = v.hours() * 3600 + u32::from(v.minutes()) * 60 + u32::from(v.seconds());
+      let serial = day_fraction(seconds, v.microseconds() * 1000);
+      sheet.write_number_with_format(row, col, serial, &formats.time)?;
+    }
+    other => {
+      let text = other.render().unwrap_or_default();
+      let text = match text.char_indices().nth(MAX_CELL_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => &text,
+      };
+      sheet.write_string(row, col, text)?;
+      return Ok(text.len() as u64);
+    }
+  }
+  Ok(NUMBER_CELL_SIZE)
+}
+
+/// Digits of a DECIMAL's text without sign, leading zeros and trailing fractional zeros
+fn significant_digits(decimal: &str) -> usize {
+  let unsigned = decimal.trim_start_matches(['-', '+']);
+  let (int, frac) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+  let frac = frac.trim_end_matches('0');
+  let digits = format!("{}{}", int, frac);
+  digits.trim_start_matches('0').len()
+}
+
+/// Dates before 1900-03-01 fall into Excel's 1900 leap year bug and are written as text
+fn excel_min_date() -> NaiveDate {
+  NaiveDate::from_ymd_opt(1900, 3, 1).unwrap_or_default()
+}
+
+/// Days since Excel's epoch of 1899-12-30
+fn date_serial(date: &NaiveDate) -> i64 {
+  let epoch = NaiveDate::from_ymd_opt(1899, 12, 30).unwrap_or_default();
+  date.signed_duration_since(epoch).num_days()
+}
+
+fn day_fraction(seconds: u32, nanos: u32) -> f64 {
+  (f64::from(seconds) + f64::from(nanos) / 1e9) / 86_400.0
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Read;
+
+  use super::*;
+
+  /// Contents of each entry of a zip archive, in archive order
+  fn read_zip(path: &std::path::Path) -> Vec<(String, String)> {
+    let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+    (0..archive.len())
+      .map(|num| {
+        let mut entry = archive.by_index(num).unwrap();
+        let mut text = String::new();
+        entry.read_to_string(&mut text).unwrap();
+        (entry.name().to_string(), text)
+      })
+      .collect()
+  }
+
+  fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("mysql2csv-{}-{}", std::process::id(), name))
+  }
+
+  #[test]
+  fn date_serials() {
+    let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+    assert_eq!(date_serial(&date(1900, 3, 1)), 61);
+    assert_eq!(date_serial(&date(2024, 1, 1)), 45_292);
+    assert_eq!(day_fraction(43_200, 0), 0.5);
+  }
+
+  #[test]
+  fn long_decimals_are_text() {
+    assert_eq!(significant_digits("-000123.4500"), 5);
+    assert_eq!(significant_digits("0.000000000000000001"), 1);
+    assert_eq!(significant_digits("1234567890.1234567"), 17);
+  }
+
+  #[test]
+  fn writes_a_readable_package() {
+    let path = temp_path("book.xlsx");
+    let mut sink: Box<dyn Sink> = Box::new(XlsxSink::new(
+      &["id", "name", "price"],
+      File::create(&path).unwrap(),
+    ));
+    sink.write_header().unwrap();
+    sink
+      .write_row(&[
+        Value::Int(1),
+        Value::Text("a & b".to_string()),
+        Value::Decimal("12345.67".to_string()),
+      ])
+      .unwrap();
+    sink
+      .write_row(&[
+        Value::Null,
+        Value::Double(1.5),
+        Value::Decimal("1234567890.1234567".to_string()),
+      ])
+      .unwrap();
+    assert!(sink.offset().unwrap() > 0);
+    sink.finish().unwrap();
+    let entries = read_zip(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let (_, sheet) = entries
+      .iter()
+      .find(|(name, _)| name == "xl/worksheets/sheet1.xml")
+      .unwrap();
+    assert!(sheet.contains("<c r=\"A2\"><v>1</v></c>"));
+    assert!(sheet.contains("a &amp; b"));
+    assert!(sheet.contains("<v>12345.67</v>"));
+    assert!(sheet.contains("1234567890.1234567"));
+    assert!(sheet.contains("<c r=\"B3\"><v>1.5</v></c>"));
+    assert!(!sheet.contains("r=\"A3\""));
+  }
+
+  #[test]
+  fn empty_exports_still_have_a_sheet() {
+    let path = temp_path("empty.xlsx");
+    let mut sink: Box<dyn Sink> = Box::new(XlsxSink::new(&["id"], File::create(&path).unwrap()));
+    sink.write_header().unwrap();
+    sink.finish().unwrap();
+    let entries = read_zip(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let (_, sheet) = entries
+      .iter()
+      .find(|(name, _)| name == "xl/worksheets/sheet1.xml")
+      .unwrap();
+    assert!(sheet.contains(">id<"));
+  }
+}